@@ -0,0 +1,157 @@
+//! Keys for maps over the elements of an `la_arena::Arena`, enabled by the `la-arena` feature.
+//! The raw index of an `Idx<T>` is used as the offset, and the key type carries `T`, so that a
+//! map cannot be accessed with indices into an arena of a different type.
+
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::ops;
+
+use la_arena::{Arena, Idx, IdxRange, RawIdx};
+
+use super::RangeKey;
+
+/// An offset or length in a `RangeMap` over the elements of an `Arena<T>`, i.e. a
+/// `RangeMap<V, ArenaKey<T>>`. Offsets are usually created from an `Idx<T>`, and lengths with
+/// `ArenaKey::len`.
+pub struct ArenaKey<T> {
+    raw: u32,
+    _ty: PhantomData<fn() -> T>,
+}
+
+impl<T> ArenaKey<T> {
+    /// A length of `len` elements.
+    pub fn len(len: u32) -> ArenaKey<T> {
+        ArenaKey { raw: len, _ty: PhantomData }
+    }
+
+    /// The number of elements in `arena`, e.g. as the size of a map covering all of them.
+    /// Panics if the arena has more elements than can be indexed.
+    pub fn arena_len(arena: &Arena<T>) -> ArenaKey<T> {
+        let len = u32::try_from(arena.len()).expect("the arena has too many elements to index");
+        ArenaKey::len(len)
+    }
+
+    /// The offset and length covering `range`, as taken by the `RangeMap` APIs.
+    pub fn offset_len(range: &IdxRange<T>) -> (ArenaKey<T>, ArenaKey<T>) {
+        let (start, end) = (ArenaKey::from(range.start()), ArenaKey::from(range.end()));
+        (start, end - start)
+    }
+
+    /// Converts a range of a map, e.g. as yielded by `RangeMap::iter`, back to arena indices.
+    pub fn idx_range(range: ops::Range<ArenaKey<T>>) -> IdxRange<T> {
+        IdxRange::new(range.start.idx()..range.end.idx())
+    }
+
+    /// The index of the arena element at this offset.
+    pub fn idx(self) -> Idx<T> {
+        Idx::from_raw(RawIdx::from(self.raw))
+    }
+}
+
+impl<T> From<Idx<T>> for ArenaKey<T> {
+    fn from(idx: Idx<T>) -> ArenaKey<T> {
+        ArenaKey::len(u32::from(idx.into_raw()))
+    }
+}
+
+// These cannot be derived, since that would require `T` to implement them as well.
+
+impl<T> Clone for ArenaKey<T> {
+    fn clone(&self) -> ArenaKey<T> {
+        *self
+    }
+}
+
+impl<T> Copy for ArenaKey<T> {}
+
+impl<T> PartialEq for ArenaKey<T> {
+    fn eq(&self, other: &ArenaKey<T>) -> bool {
+        self.raw == other.raw
+    }
+}
+
+impl<T> Eq for ArenaKey<T> {}
+
+impl<T> PartialOrd for ArenaKey<T> {
+    fn partial_cmp(&self, other: &ArenaKey<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for ArenaKey<T> {
+    fn cmp(&self, other: &ArenaKey<T>) -> Ordering {
+        self.raw.cmp(&other.raw)
+    }
+}
+
+impl<T> Hash for ArenaKey<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.raw.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for ArenaKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.raw.fmt(f)
+    }
+}
+
+impl<T> ops::Add for ArenaKey<T> {
+    type Output = ArenaKey<T>;
+
+    fn add(self, rhs: ArenaKey<T>) -> ArenaKey<T> {
+        ArenaKey::len(self.raw + rhs.raw)
+    }
+}
+
+impl<T> ops::Sub for ArenaKey<T> {
+    type Output = ArenaKey<T>;
+
+    fn sub(self, rhs: ArenaKey<T>) -> ArenaKey<T> {
+        ArenaKey::len(self.raw - rhs.raw)
+    }
+}
+
+impl<T> RangeKey for ArenaKey<T> {
+    type Offset = ArenaKey<T>;
+
+    const ZERO: ArenaKey<T> = ArenaKey { raw: 0, _ty: PhantomData };
+    const ONE: ArenaKey<T> = ArenaKey { raw: 1, _ty: PhantomData };
+
+    #[inline(always)]
+    fn from_offset(offset: ArenaKey<T>) -> ArenaKey<T> {
+        offset
+    }
+
+    #[inline(always)]
+    fn to_offset(self) -> ArenaKey<T> {
+        self
+    }
+
+    #[inline(always)]
+    fn checked_add(self, rhs: ArenaKey<T>) -> Option<ArenaKey<T>> {
+        self.raw.checked_add(rhs.raw).map(ArenaKey::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::RangeMap;
+    use super::*;
+
+    #[test]
+    fn arena_keys() {
+        let mut arena = Arena::new();
+        let first = arena.alloc("a");
+        let rest = arena.alloc_many(["b", "c", "d"]);
+        let mut map = RangeMap::<bool, ArenaKey<&str>>::new(ArenaKey::arena_len(&arena), false);
+        let (offset, len) = ArenaKey::offset_len(&rest);
+        map.insert_range(offset, len, true);
+        assert!(!*map.get(first.into()));
+        let ranges: Vec<_> =
+            map.iter_all().map(|(range, &data)| (ArenaKey::idx_range(range), data)).collect();
+        assert_eq!(ranges, vec![(IdxRange::new(first..rest.start()), false), (rest, true)]);
+    }
+}
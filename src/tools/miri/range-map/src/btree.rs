@@ -0,0 +1,191 @@
+//! A variant of `RangeMap` that stores its elements in a `BTreeMap` instead of a `Vec`.
+//! This makes splitting an element O(log n) instead of O(n), at the cost of slower lookups and
+//! iteration, so it is only worth it for maps that get split into a very large number of elements.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops;
+
+use super::{RangeKey, range_end};
+
+#[derive(Clone, Debug)]
+pub struct BTreeRangeMap<T, K = u64> {
+    /// Maps the start of each element to its end and its data. The elements are never empty and
+    /// cover the entire range of the map.
+    map: BTreeMap<K, (K, T)>,
+    /// The size of the range covered by this map.
+    size: K,
+}
+
+impl<T, K: RangeKey> BTreeRangeMap<T, K> {
+    /// Creates a new `BTreeRangeMap` for the given size, and with the given initial value used for
+    /// the entire range.
+    pub fn new(size: K::Offset, init: T) -> BTreeRangeMap<T, K> {
+        let size = K::from_offset(size);
+        let mut map = BTreeMap::new();
+        if size > K::ZERO {
+            map.insert(K::ZERO, (size, init));
+        }
+        BTreeRangeMap { map, size }
+    }
+
+    /// Returns the data associated with the given offset.
+    pub fn get(&self, offset: K::Offset) -> &T {
+        let offset = K::from_offset(offset);
+        assert!(offset < self.size, "getting an offset beyond the bounds of this RangeMap");
+        let (_, (_, data)) = self.map.range(..=offset).next_back().unwrap();
+        data
+    }
+
+    /// Provides read-only iteration over everything in the given range, see `RangeMap::iter`.
+    pub fn iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        let offset = K::from_offset(offset);
+        let end = range_end(offset, K::from_offset(len));
+        assert!(end <= self.size, "iterating beyond the bounds of this RangeMap");
+        // Start with the element containing `offset`, unless the range is empty.
+        let first =
+            if offset == end { offset } else { *self.map.range(..=offset).next_back().unwrap().0 };
+        self.map.range(first..end).map(|(&start, (end, data))| (start..*end, data))
+    }
+
+    /// Splits the element containing the given offset, such that the 2nd one starts there.
+    fn split_at(&mut self, offset: K)
+    where
+        T: Clone,
+    {
+        if let Some((_, (end, data))) = self.map.range_mut(..offset).next_back() {
+            if *end > offset {
+                let second = (*end, data.clone());
+                *end = offset;
+                self.map.insert(offset, second);
+            }
+        }
+    }
+
+    /// Merges neighbouring elements with equal data, among the elements overlapping `start..end`
+    /// and the elements right before and after them. `start..end` must not be empty.
+    fn merge_around(&mut self, start: K, end: K)
+    where
+        T: PartialEq,
+    {
+        let first = *self.map.range(..=start).next_back().unwrap().0;
+        let lo = self.map.range(..first).next_back().map_or(first, |(&start, _)| start);
+        // Pairs of the start of an element and the start of the element it gets merged into.
+        let mut merges = Vec::new();
+        let mut head: Option<(K, &T)> = None;
+        for (&start, (_, data)) in self.map.range(lo..=end) {
+            match head {
+                Some((head_start, head_data)) if head_data == data =>
+                    merges.push((start, head_start)),
+                _ => head = Some((start, data)),
+            }
+        }
+        for (start, head_start) in merges {
+            let (end, _) = self.map.remove(&start).unwrap();
+            self.map.get_mut(&head_start).unwrap().0 = end;
+        }
+    }
+
+    /// Provides mutable iteration over everything in the given range, see `RangeMap::iter_mut`.
+    /// Unlike `RangeMap::iter_mut`, this returns a plain iterator instead of a guard that merges
+    /// when dropped: equal neighbours in and around the range are merged right away, before it
+    /// is split at `offset` and `offset + len`. The mutations made through the iterator are thus
+    /// only merged by the next call that touches the same elements.
+    pub fn iter_mut(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &mut T)>
+    where
+        T: Clone + PartialEq,
+    {
+        let offset = K::from_offset(offset);
+        let end = range_end(offset, K::from_offset(len));
+        assert!(end <= self.size, "iterating beyond the bounds of this RangeMap");
+        if offset < end {
+            self.merge_around(offset, end);
+            self.split_at(offset);
+            self.split_at(end);
+        }
+        self.map.range_mut(offset..end).map(|(&start, (end, data))| (start..*end, data))
+    }
+
+    /// Overwrites everything in the given range with `data`, see `RangeMap::insert_range`.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T)
+    where
+        T: Clone,
+    {
+        let offset = K::from_offset(offset);
+        let end = range_end(offset, K::from_offset(len));
+        assert!(end <= self.size, "inserting beyond the bounds of this RangeMap");
+        if offset == end {
+            // Nothing to overwrite.
+            return;
+        }
+        self.split_at(offset);
+        self.split_at(end);
+        let covered: Vec<K> = self.map.range(offset..end).map(|(&start, _)| start).collect();
+        for start in covered {
+            self.map.remove(&start);
+        }
+        self.map.insert(offset, (end, data));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::read_range;
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let mut map = BTreeRangeMap::<i32>::new(20, -1);
+        for (_, x) in map.iter_mut(11, 1) {
+            *x = 42;
+        }
+        for (_, x) in map.iter_mut(15, 1) {
+            *x = 43;
+        }
+        assert_eq!(map.map.len(), 5);
+        assert_eq!(
+            read_range(10, 10, |i| *map.get(i)),
+            vec![-1, 42, -1, -1, -1, 43, -1, -1, -1, -1]
+        );
+        assert_eq!(
+            map.iter(11, 5).collect::<Vec<_>>(),
+            vec![(11..12, &42), (12..15, &-1), (15..16, &43)]
+        );
+        assert_eq!(map.iter(11, 0).count(), 0);
+
+        map.insert_range(10, 7, 19);
+        assert_eq!(map.map.len(), 3);
+        assert_eq!(
+            read_range(8, 12, |i| *map.get(i)),
+            vec![-1, -1, 19, 19, 19, 19, 19, 19, 19, -1, -1, -1]
+        );
+    }
+
+    #[test]
+    fn iter_mut_merges() {
+        let mut map = BTreeRangeMap::<i32>::new(20, -1);
+        assert_eq!(map.iter_mut(7, 0).count(), 0);
+        assert_eq!(map.map.len(), 1);
+        for offset in [5, 9, 7] {
+            for (_, x) in map.iter_mut(offset, 2) {
+                *x = 42;
+            }
+        }
+        assert_eq!(map.map.len(), 5);
+        // The next access merges the fragments.
+        assert_eq!(map.iter_mut(0, 20).count(), 3);
+        assert_eq!(map.map.len(), 3);
+        assert_eq!(
+            map.iter(0, 20).collect::<Vec<_>>(),
+            vec![(0..5, &-1), (5..11, &42), (11..20, &-1)]
+        );
+    }
+}
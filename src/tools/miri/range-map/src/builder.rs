@@ -0,0 +1,78 @@
+//! A builder that creates a `RangeMap` by appending runs at its end, e.g. when reconstructing a
+//! map from a snapshot. Appending never searches the map, and equal neighbours are merged as they
+//! are appended.
+
+use smallvec::SmallVec;
+
+use super::{DEFAULT_MERGE_BUDGET, Elem, Elems, RangeKey, RangeMap, range_end};
+
+#[derive(Clone, Debug)]
+pub struct RangeMapBuilder<T, K = u64> {
+    v: Elems<T, K>,
+}
+
+impl<T, K> Default for RangeMapBuilder<T, K> {
+    fn default() -> Self {
+        RangeMapBuilder { v: SmallVec::new() }
+    }
+}
+
+impl<T: PartialEq, K: RangeKey> RangeMapBuilder<T, K> {
+    /// Creates a new, empty builder.
+    pub fn new() -> RangeMapBuilder<T, K> {
+        RangeMapBuilder::default()
+    }
+
+    /// Like `new`, but reserves space for at least `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> RangeMapBuilder<T, K> {
+        RangeMapBuilder { v: SmallVec::with_capacity(capacity) }
+    }
+
+    /// Returns the end of the range covered so far, which is where the next run starts.
+    pub fn end(&self) -> K {
+        self.v.last().map_or(K::ZERO, |elem| elem.range.end)
+    }
+
+    /// Appends a run of length `len` associated with `data`. If the previous run carries equal
+    /// data, it is extended instead. Empty runs are ignored.
+    pub fn push(&mut self, len: K::Offset, data: T) {
+        let len = K::from_offset(len);
+        if len == K::ZERO {
+            return;
+        }
+        let start = self.end();
+        let end = range_end(start, len);
+        match self.v.last_mut() {
+            Some(last) if last.data == data => last.range.end = end,
+            _ => self.v.push(Elem { range: start..end, data }),
+        }
+    }
+
+    /// Returns the map made of all runs appended so far.
+    pub fn finish(self) -> RangeMap<T, K> {
+        RangeMap::from_elems(self.v, DEFAULT_MERGE_BUDGET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build() {
+        let mut builder = RangeMapBuilder::<i32>::with_capacity(3);
+        builder.push(5, -1);
+        builder.push(3, 42);
+        builder.push(0, 0);
+        builder.push(2, 42);
+        builder.push(10, -1);
+        assert_eq!(builder.end(), 20);
+        let map = builder.finish();
+        map.validate();
+        assert_eq!(
+            map.iter_all().collect::<Vec<_>>(),
+            vec![(0..5, &-1), (5..10, &42), (10..20, &-1)]
+        );
+        assert!(RangeMapBuilder::<i32>::new().finish().is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+//! A `RangeMap` that is cheap to clone: clones share their elements, which are only copied once
+//! one of the clones is mutated. This is useful when maps are cloned often (e.g. for snapshots)
+//! but most clones are never mutated.
+
+use alloc::rc::Rc;
+use core::ops;
+
+use super::{RangeKey, RangeMap};
+
+#[derive(Clone, Debug)]
+pub struct CowRangeMap<T, K = u64> {
+    map: Rc<RangeMap<T, K>>,
+}
+
+impl<T, K> ops::Deref for CowRangeMap<T, K> {
+    type Target = RangeMap<T, K>;
+
+    fn deref(&self) -> &RangeMap<T, K> {
+        &self.map
+    }
+}
+
+impl<T, K> From<RangeMap<T, K>> for CowRangeMap<T, K> {
+    fn from(map: RangeMap<T, K>) -> CowRangeMap<T, K> {
+        CowRangeMap { map: Rc::new(map) }
+    }
+}
+
+impl<T: Clone, K: RangeKey> CowRangeMap<T, K> {
+    /// Creates a new `CowRangeMap` for the given size, and with the given initial value used for
+    /// the entire range.
+    pub fn new(size: K::Offset, init: T) -> CowRangeMap<T, K> {
+        RangeMap::new(size, init).into()
+    }
+
+    /// Provides mutable access to the map. If the elements are still shared with other clones,
+    /// they are copied first.
+    pub fn to_mut(&mut self) -> &mut RangeMap<T, K> {
+        Rc::make_mut(&mut self.map)
+    }
+
+    /// Returns the map, copying its elements if they are still shared with other clones.
+    pub fn into_inner(self) -> RangeMap<T, K> {
+        Rc::try_unwrap(self.map).unwrap_or_else(|map| RangeMap::clone(&map))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_on_write() {
+        let mut map = CowRangeMap::<i32>::new(16, -1);
+        map.to_mut().insert_range(4, 8, 42);
+        let snapshot = map.clone();
+        assert!(Rc::ptr_eq(&map.map, &snapshot.map));
+        for (_, x) in &mut map.to_mut().iter_mut(0, 8) {
+            *x += 1;
+        }
+        assert!(!Rc::ptr_eq(&map.map, &snapshot.map));
+        assert_eq!(*map.get(6), 43);
+        assert_eq!(*snapshot.get(6), 42);
+        // The original is no longer shared, so this does not copy anything.
+        let map = map.into_inner();
+        assert_eq!(*map.get(0), 0);
+    }
+}
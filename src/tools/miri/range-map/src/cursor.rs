@@ -0,0 +1,191 @@
+//! Cursors over a `RangeMap`, for sequential scans. A cursor remembers the element it is
+//! positioned in, so moving it to a nearby offset does not need a binary search over the entire
+//! map.
+
+use core::iter;
+use core::ops;
+
+use super::{Elem, RangeKey, RangeMap};
+
+/// Returns the index of the element containing `offset`, or `v.len()` if `offset` is the end of
+/// the map. `hint` is the index of an element close to the result, which is checked first.
+fn locate<T, K: RangeKey>(v: &[Elem<T, K>], hint: usize, offset: K) -> usize {
+    let size = v.last().map_or(K::ZERO, |elem| elem.range.end);
+    let is_at = |idx: usize| {
+        v.get(idx).map_or(idx == v.len() && offset == size, |elem| elem.range.contains(&offset))
+    };
+    if let Some(idx) = [hint, hint + 1].into_iter().find(|&idx| is_at(idx)) {
+        return idx;
+    }
+    let idx = v.partition_point(|elem| elem.range.end <= offset);
+    assert!(is_at(idx), "seeking beyond the bounds of this RangeMap");
+    idx
+}
+
+/// A cursor over a `RangeMap`, created by `RangeMap::cursor`. The cursor is positioned at an
+/// offset, which may be the end of the map.
+#[derive(Clone, Debug)]
+pub struct Cursor<'a, T, K = u64> {
+    map: &'a RangeMap<T, K>,
+    /// The index of the element containing `offset`, or `map.v.len()` at the end of the map.
+    idx: usize,
+    offset: K,
+}
+
+impl<'a, T, K: RangeKey> Cursor<'a, T, K> {
+    /// Moves the cursor to the given offset. This is cheap if the offset is in the same or the
+    /// next element as the current position.
+    pub fn seek(&mut self, offset: K::Offset) {
+        self.offset = K::from_offset(offset);
+        self.idx = locate(&self.map.v, self.idx, self.offset);
+    }
+
+    /// Returns the offset the cursor is positioned at.
+    pub fn offset(&self) -> K::Offset {
+        self.offset.to_offset()
+    }
+
+    /// Returns the range of the element the cursor is positioned in, or `None` at the end of the
+    /// map.
+    pub fn range(&self) -> Option<ops::Range<K>> {
+        self.map.v.get(self.idx).map(|elem| elem.range.clone())
+    }
+
+    /// Returns the data associated with the cursor's position, or `None` at the end of the map.
+    pub fn value(&self) -> Option<&'a T> {
+        self.map.v.get(self.idx).map(|elem| &elem.data)
+    }
+
+    /// Moves the cursor to the start of the next element. Returns `false` if the cursor was
+    /// already at the end of the map.
+    pub fn next_run(&mut self) -> bool {
+        let Some(elem) = self.map.v.get(self.idx) else { return false };
+        self.offset = elem.range.end;
+        self.idx += 1;
+        true
+    }
+}
+
+/// A cursor over a `RangeMap` that can also overwrite the data it passes over, created by
+/// `RangeMap::cursor_mut`. The cursor is positioned at an offset, which may be the end of the map.
+#[derive(Debug)]
+pub struct CursorMut<'a, T, K = u64> {
+    map: &'a mut RangeMap<T, K>,
+    /// The index of the element containing `offset`, or `map.v.len()` at the end of the map.
+    idx: usize,
+    offset: K,
+}
+
+impl<'a, T, K: RangeKey> CursorMut<'a, T, K> {
+    /// Moves the cursor to the given offset. This is cheap if the offset is in the same or the
+    /// next element as the current position.
+    pub fn seek(&mut self, offset: K::Offset) {
+        self.offset = K::from_offset(offset);
+        self.idx = locate(&self.map.v, self.idx, self.offset);
+    }
+
+    /// Returns the offset the cursor is positioned at.
+    pub fn offset(&self) -> K::Offset {
+        self.offset.to_offset()
+    }
+
+    /// Returns the range of the element the cursor is positioned in, or `None` at the end of the
+    /// map.
+    pub fn range(&self) -> Option<ops::Range<K>> {
+        self.map.v.get(self.idx).map(|elem| elem.range.clone())
+    }
+
+    /// Returns the data associated with the cursor's position, or `None` at the end of the map.
+    pub fn value(&self) -> Option<&T> {
+        self.map.v.get(self.idx).map(|elem| &elem.data)
+    }
+
+    /// Moves the cursor to the start of the next element. Returns `false` if the cursor was
+    /// already at the end of the map.
+    pub fn next_run(&mut self) -> bool {
+        let Some(elem) = self.map.v.get(self.idx) else { return false };
+        self.offset = elem.range.end;
+        self.idx += 1;
+        true
+    }
+
+    /// Overwrites everything from the cursor's position up to `end` with `data`, and moves the
+    /// cursor to `end`.
+    pub fn set_value_until(&mut self, end: K::Offset, data: T)
+    where
+        T: Clone,
+    {
+        let end = K::from_offset(end);
+        assert!(end >= self.offset, "setting a value up to an offset before the cursor");
+        if end == self.offset {
+            return;
+        }
+        assert!(end <= self.map.size(), "inserting beyond the bounds of this RangeMap");
+        // The last element overlapping the range is the first one that reaches `end`.
+        let last_idx =
+            self.idx + self.map.v[self.idx..].partition_point(|elem| elem.range.end < end);
+        let has_head = self.map.v[self.idx].range.start < self.offset;
+        let range = self.offset..end;
+        self.map.splice_elems(self.idx..=last_idx, range.clone(), iter::once(Elem { range, data }));
+        // Skip the head (if any) and the new element.
+        self.idx += usize::from(has_head) + 1;
+        self.offset = end;
+    }
+}
+
+impl<T, K: RangeKey> RangeMap<T, K> {
+    /// Returns a cursor positioned at the given offset.
+    pub fn cursor(&self, offset: K::Offset) -> Cursor<'_, T, K> {
+        let offset = K::from_offset(offset);
+        Cursor { map: self, idx: locate(&self.v, 0, offset), offset }
+    }
+
+    /// Returns a cursor positioned at the given offset that can overwrite the map's data.
+    pub fn cursor_mut(&mut self, offset: K::Offset) -> CursorMut<'_, T, K> {
+        let offset = K::from_offset(offset);
+        let idx = locate(&self.v, 0, offset);
+        CursorMut { map: self, idx, offset }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::sample_map;
+
+    #[test]
+    fn cursor() {
+        let map = sample_map();
+        let mut cursor = map.cursor(3);
+        assert_eq!((cursor.range(), cursor.value()), (Some(0..5), Some(&-1)));
+        cursor.seek(7);
+        assert_eq!((cursor.range(), cursor.value()), (Some(5..10), Some(&42)));
+        assert!(cursor.next_run());
+        assert_eq!(cursor.offset(), 10);
+        assert_eq!(cursor.value(), Some(&-1));
+        assert!(cursor.next_run());
+        assert_eq!(cursor.offset(), 20);
+        assert_eq!(cursor.value(), None);
+        assert!(!cursor.next_run());
+        // Seeking backwards falls back to a binary search.
+        cursor.seek(0);
+        assert_eq!(cursor.range(), Some(0..5));
+    }
+
+    #[test]
+    fn cursor_mut() {
+        let mut map = sample_map();
+        let mut cursor = map.cursor_mut(2);
+        cursor.set_value_until(4, 0);
+        assert_eq!((cursor.range(), cursor.value()), (Some(4..5), Some(&-1)));
+        cursor.set_value_until(8, 1);
+        assert_eq!((cursor.range(), cursor.value()), (Some(8..10), Some(&42)));
+        cursor.seek(12);
+        cursor.set_value_until(20, 2);
+        assert_eq!(cursor.value(), None);
+        map.validate();
+        assert_eq!(
+            map.iter_all().collect::<Vec<_>>(),
+            vec![(0..2, &-1), (2..4, &0), (4..8, &1), (8..10, &42), (10..12, &-1), (12..20, &2)]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! A map from integer indices to data, where most indices are expected to keep a default value.
+//! Ranges associated with the default value are not stored at all, but synthesized when they are
+//! queried. This keeps the map small (and lookups fast) when most of it is never changed.
+
+use alloc::vec::Vec;
+use core::ops;
+
+use super::{RangeKey, SparseRangeMap};
+
+#[derive(Clone, Debug)]
+pub struct DefaultRangeMap<T, K = u64> {
+    /// Everything that is not associated with `default`. Gaps in this map are associated with
+    /// `default`.
+    map: SparseRangeMap<T, K>,
+    default: T,
+}
+
+impl<T: Clone + PartialEq, K: RangeKey> DefaultRangeMap<T, K> {
+    /// Creates a new `DefaultRangeMap` for the given size, where everything is associated with
+    /// `default`.
+    pub fn new(size: K::Offset, default: T) -> DefaultRangeMap<T, K> {
+        DefaultRangeMap { map: SparseRangeMap::new(size), default }
+    }
+
+    /// Returns the data associated with the given offset.
+    pub fn get(&self, offset: K::Offset) -> &T {
+        self.map.get(offset).unwrap_or(&self.default)
+    }
+
+    /// Provides read-only iteration over everything in the given range, including the ranges
+    /// associated with the default value. The provided ranges are clipped to the given range.
+    pub fn iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        self.map.iter(offset, len).map(|(range, data)| (range, data.unwrap_or(&self.default)))
+    }
+
+    /// Overwrites everything in the given range with `data`. If that is the default value, the
+    /// range is removed from storage.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T) {
+        if data == self.default {
+            self.map.remove_range(offset, len);
+        } else {
+            self.map.insert_range(offset, len, data);
+        }
+    }
+
+    /// Applies `f` to everything in the given range. Ranges with the default value are passed
+    /// to `f` as a copy, and only stored if `f` changes them; ranges that `f` changes back to the
+    /// default value are removed from storage. Returns whether `f` changed any value.
+    pub fn update_range(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        mut f: impl FnMut(&mut T),
+    ) -> bool {
+        let updates: Vec<_> = self
+            .iter(offset, len)
+            .filter_map(|(range, data)| {
+                let mut new = data.clone();
+                f(&mut new);
+                (new != *data).then_some((range, new))
+            })
+            .collect();
+        let changed = !updates.is_empty();
+        for (range, data) in updates {
+            self.insert_range(range.start.to_offset(), (range.end - range.start).to_offset(), data);
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::read_range;
+    use super::*;
+
+    #[test]
+    fn elides_default() {
+        let mut map = DefaultRangeMap::<i32>::new(1u64 << 40, 0);
+        map.insert_range(2, 4, 42);
+        assert_eq!(map.iter(0, 8).collect::<Vec<_>>(), vec![(0..2, &0), (2..6, &42), (6..8, &0)]);
+
+        assert!(map.update_range(4, 4, |x| *x += 1));
+        assert_eq!(read_range(1, 8, |i| *map.get(i)), vec![0, 42, 42, 43, 43, 1, 1, 0]);
+        // Writing the default value removes the range from storage.
+        map.insert_range(0, 5, 0);
+        assert!(map.update_range(5, 3, |x| *x = 0));
+        assert!(!map.update_range(5, 3, |x| *x = 0));
+        assert_eq!(map.map.iter(0, 8).count(), 1);
+        assert_eq!(read_range(0, 8, |i| *map.get(i)), vec![0; 8]);
+    }
+}
@@ -0,0 +1,90 @@
+//! An entry API for `RangeMap`, in the style of `HashMap::entry`. An entry refers to a range of
+//! the map, and elements are only split once a mutation actually happens.
+
+use core::ops;
+
+use super::{RangeKey, RangeMap};
+
+/// A range of a `RangeMap`, created by `RangeMap::entry`.
+#[derive(Debug)]
+pub struct Entry<'a, T, K: RangeKey = u64> {
+    map: &'a mut RangeMap<T, K>,
+    offset: K::Offset,
+    len: K::Offset,
+}
+
+impl<'a, T, K: RangeKey> Entry<'a, T, K> {
+    /// Returns the range this entry refers to.
+    pub fn range(&self) -> ops::Range<K> {
+        let offset = K::from_offset(self.offset);
+        offset..offset + K::from_offset(self.len)
+    }
+
+    /// Overwrites the entire range with `data`.
+    pub fn set(self, data: T)
+    where
+        T: Clone,
+    {
+        self.map.insert_range(self.offset, self.len, data);
+    }
+
+    /// Applies `f` to everything in the range. Elements are only split where `f` actually
+    /// changes the data, see `RangeMap::update_range`.
+    pub fn and_modify(self, f: impl FnMut(&mut T)) -> Self
+    where
+        T: Clone + PartialEq,
+    {
+        self.map.update_range(self.offset, self.len, f);
+        self
+    }
+}
+
+impl<'a, T: Clone, K: RangeKey> Entry<'a, Option<T>, K> {
+    /// Fills everything in the range that is `None` with the result of `f`. Elements that are
+    /// already `Some` are neither split nor changed.
+    pub fn or_insert_with(self, mut f: impl FnMut() -> T) -> Self {
+        self.map.for_each_mut(self.offset, self.len, |_, data| {
+            if data.is_some() {
+                return false;
+            }
+            *data = Some(f());
+            true
+        });
+        self
+    }
+}
+
+impl<T, K: RangeKey> RangeMap<T, K> {
+    /// Returns an entry for the given range, for in-place manipulation.
+    pub fn entry(&mut self, offset: K::Offset, len: K::Offset) -> Entry<'_, T, K> {
+        self.check_bounds(offset, len).unwrap_or_else(|err| panic!("{err}"));
+        Entry { map: self, offset, len }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry() {
+        let mut map = RangeMap::<Option<i32>>::new(20, None);
+        map.entry(5, 5).set(Some(42));
+        let mut calls = 0;
+        let entry = map.entry(0, 8).or_insert_with(|| {
+            calls += 1;
+            0
+        });
+        assert_eq!(entry.range(), 0..8);
+        entry.and_modify(|x| *x = x.map(|x| x + 1));
+        assert_eq!(calls, 1);
+        assert_eq!(
+            map.iter_all().collect::<Vec<_>>(),
+            vec![(0..5, &Some(1)), (5..8, &Some(43)), (8..10, &Some(42)), (10..20, &None)]
+        );
+        // Nothing to do, so nothing is split.
+        map.entry(12, 2).and_modify(|_| ());
+        map.entry(6, 2).or_insert_with(|| 0);
+        assert_eq!(map.num_runs(), 4);
+    }
+}
@@ -0,0 +1,114 @@
+//! A naive reference implementation of `RangeMap` that stores one value per index, for
+//! differential testing: apply the same operations to a `FlatMap` and a `RangeMap`, and check
+//! that they agree with `assert_matches`. Available in tests, and with the `reference` feature.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::{RangeKey, RangeMap};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlatMap<T> {
+    v: Vec<T>,
+}
+
+impl<T: Clone> FlatMap<T> {
+    /// Creates a new `FlatMap` for the given size, and with the given initial value used for
+    /// every index.
+    pub fn new(size: usize, init: T) -> FlatMap<T> {
+        FlatMap { v: alloc::vec![init; size] }
+    }
+
+    /// Returns the data associated with the given index.
+    pub fn get(&self, index: usize) -> &T {
+        &self.v[index]
+    }
+
+    /// Overwrites everything in the given range with `data`.
+    pub fn insert_range(&mut self, offset: usize, len: usize, data: T) {
+        self.v[offset..offset + len].fill(data);
+    }
+
+    /// Applies `f` to the data of every index in the given range.
+    pub fn update_range(&mut self, offset: usize, len: usize, f: impl FnMut(&mut T)) {
+        self.v[offset..offset + len].iter_mut().for_each(f);
+    }
+
+    /// Copies data within the map, like `RangeMap::copy_within`.
+    pub fn copy_within(&mut self, src: usize, dst: usize, len: usize) {
+        let data = self.v[src..src + len].to_vec();
+        self.v[dst..dst + len].clone_from_slice(&data);
+    }
+
+    /// Panics if `map` is not a valid map that associates the same data with every index as this
+    /// map.
+    pub fn assert_matches<K>(&self, map: &RangeMap<T, K>)
+    where
+        T: PartialEq + fmt::Debug,
+        K: RangeKey + TryInto<usize>,
+    {
+        map.validate();
+        let index = |k: K| k.try_into().unwrap_or_else(|_| panic!("index {k:?} is too large"));
+        assert_eq!(index(map.size()), self.v.len(), "the maps have different sizes");
+        for (range, data) in map.iter_all() {
+            let (start, end) = (index(range.start), index(range.end));
+            if let Some(i) = (start..end).find(|&i| self.get(i) != data) {
+                panic!("index {i} maps to {data:?}, but should map to {:?}", self.get(i));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tiny deterministic pseudo-random number generator, so that the test does not need any
+    /// dependencies and failures are reproducible.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 33) % n
+        }
+    }
+
+    #[test]
+    fn random_operations() {
+        const SIZE: u64 = 64;
+        let mut rng = Lcg(42);
+        let mut map = RangeMap::<u64>::new(SIZE, 0);
+        let mut flat = FlatMap::new(usize::try_from(SIZE).unwrap(), 0);
+        for _ in 0..1000 {
+            let offset = rng.below(SIZE);
+            let len = rng.below(SIZE - offset + 1);
+            let value = rng.below(4);
+            let (o, l) = (usize::try_from(offset).unwrap(), usize::try_from(len).unwrap());
+            match rng.below(4) {
+                0 => {
+                    map.insert_range(offset, len, value);
+                    flat.insert_range(o, l, value);
+                }
+                1 => {
+                    for (_, x) in &mut map.iter_mut(offset, len) {
+                        *x = (*x + value) % 4;
+                    }
+                    flat.update_range(o, l, |x| *x = (*x + value) % 4);
+                }
+                2 => {
+                    map.update_range(offset, len, |x| {
+                        *x = x.saturating_sub(value);
+                    });
+                    flat.update_range(o, l, |x| *x = x.saturating_sub(value));
+                }
+                _ => {
+                    let dst = rng.below(SIZE - len + 1);
+                    map.copy_within(offset, dst, len);
+                    flat.copy_within(o, usize::try_from(dst).unwrap(), l);
+                }
+            }
+            flat.assert_matches(&map);
+        }
+    }
+}
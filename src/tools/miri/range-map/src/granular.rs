@@ -0,0 +1,130 @@
+//! A `RangeMap` whose elements never cross a multiple of a fixed granularity (e.g. the page
+//! size). Operations on whole pages, like dirty tracking or protection changes, then never need
+//! to split an element; the splitting is done when the data is written instead.
+
+use alloc::vec::Vec;
+use core::ops;
+
+use super::{Elem, IterMut, RangeKey, RangeMap, range_end, splice};
+
+#[derive(Clone, Debug)]
+pub struct GranularRangeMap<T, K = u64> {
+    /// The map. Its merge budget is 0, since merging might create elements that cross a
+    /// granularity boundary; see `merge_adjacent` for merging within the boundaries.
+    map: RangeMap<T, K>,
+    granularity: K,
+}
+
+impl<T, K> ops::Deref for GranularRangeMap<T, K> {
+    type Target = RangeMap<T, K>;
+
+    fn deref(&self) -> &RangeMap<T, K> {
+        &self.map
+    }
+}
+
+impl<T: Clone, K: RangeKey + ops::Rem<Output = K>> GranularRangeMap<T, K> {
+    /// Creates a new `GranularRangeMap` for the given size, and with the given initial value used
+    /// for the entire range. Elements are split at every multiple of `granularity`.
+    pub fn new(size: K::Offset, init: T, granularity: K::Offset) -> GranularRangeMap<T, K> {
+        let granularity = K::from_offset(granularity);
+        assert!(granularity > K::ZERO, "the granularity of a GranularRangeMap must not be 0");
+        let mut map = RangeMap::new(size, init);
+        map.set_merge_budget(0);
+        let mut map = GranularRangeMap { map, granularity };
+        map.split_at_granularity(K::ZERO, K::from_offset(size));
+        map
+    }
+
+    /// Returns the granularity at which elements are split.
+    pub fn granularity(&self) -> K {
+        self.granularity
+    }
+
+    /// Splits all elements overlapping `start..end` at every multiple of the granularity.
+    fn split_at_granularity(&mut self, start: K, end: K) {
+        if start == end {
+            return;
+        }
+        let first = self.map.find_offset(start);
+        let last = self.map.find_offset(end - K::ONE);
+        let granularity = self.granularity;
+        let boundary_after = |offset: K| offset - offset % granularity + granularity;
+        let elems = &self.map.v[first..=last];
+        if elems.iter().all(|elem| elem.range.end <= boundary_after(elem.range.start)) {
+            // Nothing to split.
+            return;
+        }
+        let mut split = Vec::with_capacity(elems.len());
+        for elem in elems {
+            let mut pos = elem.range.start;
+            while pos < elem.range.end {
+                let piece_end = boundary_after(pos).min(elem.range.end);
+                split.push(Elem { range: pos..piece_end, data: elem.data.clone() });
+                pos = piece_end;
+            }
+        }
+        splice(&mut self.map.v, first..last + 1, split);
+    }
+
+    /// Provides mutable iteration over everything in the given range, see `RangeMap::iter_mut`.
+    /// Unlike there, nothing gets merged; use `merge_adjacent` for that.
+    pub fn iter_mut(&mut self, offset: K::Offset, len: K::Offset) -> IterMut<'_, T, K>
+    where
+        T: PartialEq,
+    {
+        self.map.iter_mut(offset, len)
+    }
+
+    /// Overwrites everything in the given range with `data`, see `RangeMap::insert_range`.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T) {
+        self.map.insert_range(offset, len, data);
+        let start = K::from_offset(offset);
+        self.split_at_granularity(start, range_end(start, K::from_offset(len)));
+    }
+
+    /// Copies data within the map, see `RangeMap::copy_within`.
+    pub fn copy_within(&mut self, src: K::Offset, dst: K::Offset, len: K::Offset) {
+        self.map.copy_within(src, dst, len);
+        let start = K::from_offset(dst);
+        self.split_at_granularity(start, range_end(start, K::from_offset(len)));
+    }
+
+    /// Merges all neighbouring elements with equal data, unless they are separated by a multiple
+    /// of the granularity.
+    pub fn merge_adjacent(&mut self)
+    where
+        T: PartialEq,
+    {
+        let granularity = self.granularity;
+        self.map.v.dedup_by(|elem, prev| {
+            if elem.data != prev.data || elem.range.start % granularity == K::ZERO {
+                return false;
+            }
+            prev.range.end = elem.range.end;
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn granularity() {
+        let mut map = GranularRangeMap::<i32>::new(20, -1, 8);
+        assert_eq!(map.ranges().collect::<Vec<_>>(), vec![0..8, 8..16, 16..20]);
+        map.insert_range(5, 9, 42);
+        assert_eq!(map.ranges().collect::<Vec<_>>(), vec![0..5, 5..8, 8..14, 14..16, 16..20]);
+        map.copy_within(2, 10, 8);
+        for (range, _) in map.iter_all() {
+            assert_eq!(range.start / 8, (range.end - 1) / 8, "{range:?} crosses a boundary");
+        }
+        for (_, x) in &mut map.iter_mut(0, 20) {
+            *x = 0;
+        }
+        map.merge_adjacent();
+        assert_eq!(map.ranges().collect::<Vec<_>>(), vec![0..8, 8..16, 16..20]);
+    }
+}
@@ -0,0 +1,251 @@
+//! A `RangeMap` that interns its data: every distinct value is stored only once, and the map
+//! itself only stores small handles referring to those values. This saves memory when the data is
+//! large and the same values are repeated across many ranges, like borrow stacks, and splitting
+//! an element only copies its handle, never the data.
+//!
+//! Which table is used to look up the handle of a value is up to the `HandleTable` parameter: by
+//! default, values are looked up in a `BTreeMap`, so they need to be `Ord`. A `DedupRangeMap`
+//! looks them up by their hash instead, for values that are only `Eq + Hash`. Since hash maps are
+//! not available without `std`, the hasher is a parameter, e.g. `BuildHasherDefault<FxHasher>`.
+
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash};
+use core::ops;
+
+use smallvec::SmallVec;
+
+use super::{RangeKey, RangeMap};
+
+/// Finds the handles of the values interned by an `InternedRangeMap`.
+pub trait HandleTable<T> {
+    /// Returns the handle of `data`, given the interned values indexed by their handles.
+    fn get(&self, data: &T, values: &[Rc<T>]) -> Option<usize>;
+    /// Records that `data` is interned with the given handle.
+    fn insert(&mut self, data: &Rc<T>, handle: usize);
+    /// Renumbers the handles, after the values that are not used anymore have been removed.
+    /// `remap` gives the new handle for each old one, or `None` if the value was removed.
+    fn remap(&mut self, remap: &[Option<usize>]);
+}
+
+impl<T: Ord> HandleTable<T> for BTreeMap<Rc<T>, usize> {
+    fn get(&self, data: &T, _values: &[Rc<T>]) -> Option<usize> {
+        BTreeMap::get(self, data).copied()
+    }
+
+    fn insert(&mut self, data: &Rc<T>, handle: usize) {
+        BTreeMap::insert(self, Rc::clone(data), handle);
+    }
+
+    fn remap(&mut self, remap: &[Option<usize>]) {
+        self.retain(|_, handle| {
+            let Some(new) = remap[*handle] else { return false };
+            *handle = new;
+            true
+        });
+    }
+}
+
+/// A `HandleTable` for values that are `Eq + Hash`, hashed by `S`. Values with equal hashes
+/// share a bucket, in which they are compared by `Eq`.
+#[derive(Clone, Debug, Default)]
+pub struct HashTable<S> {
+    buckets: BTreeMap<u64, SmallVec<[usize; 1]>>,
+    hasher: S,
+}
+
+impl<T: Eq + Hash, S: BuildHasher> HandleTable<T> for HashTable<S> {
+    fn get(&self, data: &T, values: &[Rc<T>]) -> Option<usize> {
+        let bucket = self.buckets.get(&self.hasher.hash_one(data))?;
+        bucket.iter().copied().find(|&handle| *values[handle] == *data)
+    }
+
+    fn insert(&mut self, data: &Rc<T>, handle: usize) {
+        self.buckets.entry(self.hasher.hash_one(&**data)).or_default().push(handle);
+    }
+
+    fn remap(&mut self, remap: &[Option<usize>]) {
+        self.buckets.retain(|_, bucket| {
+            bucket.retain(|handle| remap[*handle].is_some());
+            bucket.iter_mut().for_each(|handle| *handle = remap[*handle].unwrap());
+            !bucket.is_empty()
+        });
+    }
+}
+
+/// An `InternedRangeMap` for values that are `Eq + Hash` rather than `Ord`, like borrow stacks.
+pub type DedupRangeMap<T, S, K = u64> = InternedRangeMap<T, K, HashTable<S>>;
+
+#[derive(Clone, Debug)]
+pub struct InternedRangeMap<T, K = u64, H = BTreeMap<Rc<T>, usize>> {
+    /// Maps every range to the index of its data in `values`.
+    map: RangeMap<usize, K>,
+    /// All values that have been interned so far. Values that are no longer referenced by `map`
+    /// are only removed by `collect_garbage`.
+    values: Vec<Rc<T>>,
+    /// Maps every value in `values` to its index.
+    handles: H,
+}
+
+impl<T, K: RangeKey, H: HandleTable<T>> InternedRangeMap<T, K, H> {
+    /// Creates a new `InternedRangeMap` for the given size, and with the given initial value used
+    /// for the entire range.
+    pub fn new(size: K::Offset, init: T) -> InternedRangeMap<T, K, H>
+    where
+        H: Default,
+    {
+        let init = Rc::new(init);
+        let mut handles = H::default();
+        handles.insert(&init, 0);
+        InternedRangeMap { map: RangeMap::new(size, 0), values: Vec::from([init]), handles }
+    }
+
+    /// Returns the handle of `data`, adding it to the interned values if necessary.
+    fn intern(&mut self, data: T) -> usize {
+        if let Some(handle) = self.handles.get(&data, &self.values) {
+            return handle;
+        }
+        let data = Rc::new(data);
+        let handle = self.values.len();
+        self.handles.insert(&data, handle);
+        self.values.push(data);
+        handle
+    }
+
+    /// Returns the data associated with the given offset.
+    pub fn get(&self, offset: K::Offset) -> &T {
+        &self.values[*self.map.get(offset)]
+    }
+
+    /// Provides read-only iteration over everything in the given range. The provided ranges are
+    /// not clipped to the given range.
+    pub fn iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        self.map.iter(offset, len).map(|(range, &handle)| (range, &*self.values[handle]))
+    }
+
+    /// Overwrites everything in the given range with `data`.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T) {
+        let handle = self.intern(data);
+        self.map.insert_range(offset, len, handle);
+    }
+
+    /// Applies `f` to a copy of the data of everything in the given range, and interns the
+    /// results. `f` is called only once for every distinct value in the range. Returns whether
+    /// `f` changed any value.
+    pub fn update_range(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        mut f: impl FnMut(&mut T),
+    ) -> bool
+    where
+        T: Clone,
+    {
+        // Compute the new handles first, since interning and `iter_mut` both need to borrow
+        // `self` mutably.
+        let mut old: Vec<usize> = self.map.iter(offset, len).map(|(_, &handle)| handle).collect();
+        old.sort_unstable();
+        old.dedup();
+        let mut updates = BTreeMap::new();
+        for handle in old {
+            let mut data = T::clone(&self.values[handle]);
+            f(&mut data);
+            updates.insert(handle, self.intern(data));
+        }
+        let mut changed = false;
+        for (_, handle) in &mut self.map.iter_mut(offset, len) {
+            let new = updates[handle];
+            changed |= new != *handle;
+            *handle = new;
+        }
+        changed
+    }
+
+    /// Returns the number of distinct values that are currently interned.
+    pub fn num_values(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Removes all interned values that are no longer associated with any range.
+    pub fn collect_garbage(&mut self) {
+        let mut used = alloc::vec![false; self.values.len()];
+        for (_, &handle) in self.map.iter_all() {
+            used[handle] = true;
+        }
+        // Compute the new handle of every value that is still used, and renumber the map.
+        let mut remap = Vec::with_capacity(self.values.len());
+        let mut values = Vec::new();
+        for (data, used) in self.values.drain(..).zip(used) {
+            remap.push(used.then_some(values.len()));
+            if used {
+                values.push(data);
+            }
+        }
+        for (_, handle) in self.map.iter_mut_all() {
+            *handle = remap[*handle].unwrap();
+        }
+        self.handles.remap(&remap);
+        self.values = values;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    use super::super::tests::read_range;
+    use super::*;
+
+    #[test]
+    fn shares_equal_values() {
+        let mut map = InternedRangeMap::<i32>::new(20, -1);
+        for i in 0..5 {
+            map.insert_range(i * 4, 2, 42);
+        }
+        assert_eq!(map.num_values(), 2);
+        assert_eq!(read_range(0, 6, |i| *map.get(i)), vec![42, 42, -1, -1, 42, 42]);
+
+        assert!(map.update_range(1, 4, |x| *x += 1));
+        assert_eq!(read_range(0, 6, |i| *map.get(i)), vec![42, 43, 0, 0, 43, 42]);
+        assert_eq!(map.num_values(), 4);
+        assert!(!map.update_range(0, 20, |_| ()));
+
+        // Afterwards, -1 is not used anywhere anymore.
+        map.insert_range(6, 14, 0);
+        map.collect_garbage();
+        assert_eq!(map.num_values(), 3);
+        assert_eq!(read_range(0, 8, |i| *map.get(i)), vec![42, 43, 0, 0, 43, 42, 0, 0]);
+        map.insert_range(0, 1, -1);
+        assert_eq!(map.num_values(), 4);
+        assert_eq!(read_range(0, 3, |i| *map.get(i)), vec![-1, 43, 0]);
+    }
+
+    /// A value that is `Eq + Hash`, but not `Ord`.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct Stack(Vec<u32>);
+
+    #[test]
+    fn dedup() {
+        type Map = DedupRangeMap<Stack, BuildHasherDefault<DefaultHasher>>;
+        let mut map = Map::new(20, Stack(vec![]));
+        for i in 0..5 {
+            map.insert_range(i * 4, 2, Stack(vec![1]));
+        }
+        assert_eq!(map.num_values(), 2);
+        assert!(map.update_range(1, 4, |s| s.0.push(2)));
+        assert_eq!(map.num_values(), 4);
+        assert_eq!(*map.get(2), Stack(vec![2]));
+        map.insert_range(0, 20, Stack(vec![1, 2]));
+        map.collect_garbage();
+        assert_eq!(map.num_values(), 1);
+        map.insert_range(3, 1, Stack(vec![2]));
+        assert_eq!(map.num_values(), 2);
+        assert_eq!(map.iter(0, 20).count(), 3);
+    }
+}
@@ -0,0 +1,155 @@
+//! Guards providing mutable access to the elements of a `RangeMap`, returned by `iter_mut` and
+//! friends. The elements are split before they are handed out, and equal neighbours are merged
+//! again once the guard is dropped, i.e. after the mutation, so that the merge sees the new data.
+//!
+//! The elements are only reachable through a mutable borrow of the guard, so no reference to
+//! them can outlive it; that is what makes it possible to merge (and thus move) them on drop.
+
+use alloc::rc::Rc;
+use core::iter;
+use core::mem;
+use core::ops;
+use core::slice;
+
+use smallvec::SmallVec;
+
+use super::{Elem, RangeKey, RangeMap};
+
+/// The elements covering one of the ranges accessed through an `IterMut`, with their ranges.
+pub type ElemsMut<'g, T, K> =
+    iter::Map<slice::IterMut<'g, Elem<T, K>>, fn(&'g mut Elem<T, K>) -> (ops::Range<K>, &'g mut T)>;
+
+/// Mutable access to one or more ranges of a `RangeMap`. Iterating over a mutable reference to it
+/// yields the elements covering these ranges, e.g. `for (range, data) in &mut map.iter_mut(..)`.
+/// Once it is dropped, equal neighbours in and around the accessed ranges are merged, subject to
+/// the merge budget.
+pub struct IterMut<'a, T: PartialEq, K: RangeKey = u64> {
+    map: &'a mut RangeMap<T, K>,
+    /// The indices of the elements covering each accessed range. These are sorted and disjoint.
+    idxs: SmallVec<[ops::Range<usize>; 1]>,
+}
+
+impl<'a, T: PartialEq, K: RangeKey> IterMut<'a, T, K> {
+    pub(super) fn new(
+        map: &'a mut RangeMap<T, K>,
+        idxs: SmallVec<[ops::Range<usize>; 1]>,
+    ) -> IterMut<'a, T, K> {
+        IterMut { map, idxs }
+    }
+
+    /// Provides one iterator per accessed range, in order, see `RangeMap::iter_mut_multi`.
+    pub fn by_range(&mut self) -> ByRange<'_, T, K> {
+        ByRange { rest: &mut self.map.v[..], rest_start: 0, idxs: self.idxs.iter() }
+    }
+}
+
+impl<'g, T: PartialEq, K: RangeKey> IntoIterator for &'g mut IterMut<'_, T, K> {
+    type Item = (ops::Range<K>, &'g mut T);
+    type IntoIter = iter::Flatten<ByRange<'g, T, K>>;
+
+    fn into_iter(self) -> iter::Flatten<ByRange<'g, T, K>> {
+        self.by_range().flatten()
+    }
+}
+
+impl<T: PartialEq, K: RangeKey> Drop for IterMut<'_, T, K> {
+    fn drop(&mut self) {
+        let mut nonempty = self.idxs.iter().filter(|idxs| !idxs.is_empty());
+        let Some(first) = nonempty.next() else { return };
+        let last = nonempty.next_back().unwrap_or(first);
+        // Everything between the first and the last range gets merged. Also look at the elements
+        // right next to that, since the mutated elements might now be equal to them.
+        let window = first.start.saturating_sub(1)..(last.end + 1).min(self.map.v.len());
+        self.map.merge_window(window, |a, b| a == b);
+    }
+}
+
+/// Iterator over the ranges accessed through an `IterMut`, see `IterMut::by_range`.
+pub struct ByRange<'g, T, K> {
+    /// The elements not handed out yet, starting at index `rest_start` of the map.
+    rest: &'g mut [Elem<T, K>],
+    rest_start: usize,
+    idxs: slice::Iter<'g, ops::Range<usize>>,
+}
+
+fn elem_mut<T, K: Clone>(elem: &mut Elem<T, K>) -> (ops::Range<K>, &mut T) {
+    (elem.range.clone(), &mut elem.data)
+}
+
+impl<'g, T, K: RangeKey> Iterator for ByRange<'g, T, K> {
+    type Item = ElemsMut<'g, T, K>;
+
+    fn next(&mut self) -> Option<ElemsMut<'g, T, K>> {
+        let idxs = self.idxs.next()?;
+        let elems = if idxs.is_empty() {
+            &mut []
+        } else {
+            let (_, tail) = mem::take(&mut self.rest).split_at_mut(idxs.start - self.rest_start);
+            let (elems, tail) = tail.split_at_mut(idxs.len());
+            self.rest = tail;
+            self.rest_start = idxs.end;
+            elems
+        };
+        Some(elems.iter_mut().map(elem_mut as _))
+    }
+}
+
+impl<'g, T, K: RangeKey> DoubleEndedIterator for ByRange<'g, T, K> {
+    fn next_back(&mut self) -> Option<ElemsMut<'g, T, K>> {
+        let idxs = self.idxs.next_back()?;
+        let elems = if idxs.is_empty() {
+            &mut []
+        } else {
+            let (head, tail) = mem::take(&mut self.rest).split_at_mut(idxs.start - self.rest_start);
+            self.rest = head;
+            &mut tail[..idxs.len()]
+        };
+        Some(elems.iter_mut().map(elem_mut as _))
+    }
+}
+
+/// Mutable access to the data associated with a single offset, see `RangeMap::get_mut`. Once it
+/// is dropped, the element is merged with its neighbours if they are equal.
+pub struct DataMut<'a, T: PartialEq, K: RangeKey = u64>(IterMut<'a, T, K>);
+
+impl<'a, T: PartialEq, K: RangeKey> DataMut<'a, T, K> {
+    pub(super) fn new(map: &'a mut RangeMap<T, K>, idx: usize) -> DataMut<'a, T, K> {
+        DataMut(IterMut::new(map, smallvec::smallvec![idx..idx + 1]))
+    }
+}
+
+impl<T: PartialEq, K: RangeKey> ops::Deref for DataMut<'_, T, K> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.map.v[self.0.idxs[0].start].data
+    }
+}
+
+impl<T: PartialEq, K: RangeKey> ops::DerefMut for DataMut<'_, T, K> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0.map.v[self.0.idxs[0].start].data
+    }
+}
+
+/// Like `IterMut`, but provides mutable access to the data behind the `Rc`s, see
+/// `RangeMap::iter_mut_shared`.
+pub struct IterMutShared<'a, T: Clone + PartialEq, K: RangeKey = u64>(IterMut<'a, Rc<T>, K>);
+
+impl<'a, T: Clone + PartialEq, K: RangeKey> IterMutShared<'a, T, K> {
+    pub(super) fn new(iter: IterMut<'a, Rc<T>, K>) -> IterMutShared<'a, T, K> {
+        IterMutShared(iter)
+    }
+}
+
+impl<'g, T: Clone + PartialEq, K: RangeKey> IntoIterator for &'g mut IterMutShared<'_, T, K> {
+    type Item = (ops::Range<K>, &'g mut T);
+    type IntoIter = iter::Map<
+        iter::Flatten<ByRange<'g, Rc<T>, K>>,
+        fn((ops::Range<K>, &'g mut Rc<T>)) -> (ops::Range<K>, &'g mut T),
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.by_range().flatten().map(|(range, data)| (range, Rc::make_mut(data)))
+    }
+}
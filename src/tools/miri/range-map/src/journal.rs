@@ -0,0 +1,160 @@
+//! A `RangeMap` that records every mutation in a journal, which can be replayed onto a fresh map
+//! to reconstruct exactly how the map got into its current shape, e.g. to track down where two
+//! runs of the interpreter diverge.
+//!
+//! `RangeMap` is deterministic, so replaying the same mutations also reproduces the same splits
+//! and merges; they do not need to be recorded separately. Mutations that take a closure record
+//! the values the closure produced rather than the closure itself.
+
+use alloc::vec::Vec;
+use core::ops;
+
+use super::{RangeKey, RangeMap, range_end};
+
+/// A single mutation recorded by a `JournaledRangeMap`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JournalOp<T, K = u64> {
+    /// `RangeMap::insert_range` of `data` over `range`.
+    InsertRange { range: ops::Range<K>, data: T },
+    /// `RangeMap::update_range` over `range`, where the closure produced `results`, in the order
+    /// in which it was called.
+    UpdateRange { range: ops::Range<K>, results: Vec<T> },
+    /// `RangeMap::copy_within` of `len` bytes from `src` to `dst`.
+    CopyWithin { src: K, dst: K, len: K },
+    /// `RangeMap::merge_adjacent_thorough`.
+    MergeAdjacent,
+}
+
+impl<T: Clone + PartialEq, K: RangeKey> JournalOp<T, K> {
+    /// Applies this mutation to `map`.
+    pub fn apply(&self, map: &mut RangeMap<T, K>) {
+        match self {
+            JournalOp::InsertRange { range, data } => {
+                let (offset, len) = (range.start, range.end - range.start);
+                map.insert_range(offset.to_offset(), len.to_offset(), data.clone());
+            }
+            JournalOp::UpdateRange { range, results } => {
+                let (offset, len) = (range.start, range.end - range.start);
+                let mut results = results.iter();
+                map.update_range(offset.to_offset(), len.to_offset(), |data| {
+                    *data = results.next().expect("journal is missing update results").clone();
+                });
+                assert!(results.next().is_none(), "journal has too many update results");
+            }
+            JournalOp::CopyWithin { src, dst, len } => {
+                map.copy_within(src.to_offset(), dst.to_offset(), len.to_offset());
+            }
+            JournalOp::MergeAdjacent => map.merge_adjacent_thorough(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct JournaledRangeMap<T, K = u64> {
+    map: RangeMap<T, K>,
+    /// The size and initial value the map was created with, which is where replay starts.
+    size: K,
+    init: T,
+    journal: Vec<JournalOp<T, K>>,
+}
+
+impl<T, K> ops::Deref for JournaledRangeMap<T, K> {
+    type Target = RangeMap<T, K>;
+
+    fn deref(&self) -> &RangeMap<T, K> {
+        &self.map
+    }
+}
+
+impl<T: Clone + PartialEq, K: RangeKey> JournaledRangeMap<T, K> {
+    /// Creates a new `JournaledRangeMap` for the given size, and with the given initial value
+    /// used for the entire range.
+    pub fn new(size: K::Offset, init: T) -> JournaledRangeMap<T, K> {
+        JournaledRangeMap {
+            map: RangeMap::new(size, init.clone()),
+            size: K::from_offset(size),
+            init,
+            journal: Vec::new(),
+        }
+    }
+
+    /// Returns all mutations since the map was created, in order.
+    pub fn journal(&self) -> &[JournalOp<T, K>] {
+        &self.journal
+    }
+
+    /// Replays the journal onto a fresh map. The result is split exactly like this map.
+    pub fn replay(&self) -> RangeMap<T, K> {
+        let mut map = RangeMap::new(self.size.to_offset(), self.init.clone());
+        for op in &self.journal {
+            op.apply(&mut map);
+        }
+        map
+    }
+
+    fn range(offset: K::Offset, len: K::Offset) -> ops::Range<K> {
+        let offset = K::from_offset(offset);
+        offset..range_end(offset, K::from_offset(len))
+    }
+
+    /// Overwrites everything in the given range with `data`, see `RangeMap::insert_range`.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T) {
+        self.journal
+            .push(JournalOp::InsertRange { range: Self::range(offset, len), data: data.clone() });
+        self.map.insert_range(offset, len, data);
+    }
+
+    /// Applies `f` to everything in the given range, see `RangeMap::update_range`. There is no
+    /// journaled `iter_mut`, since the values written through it cannot be observed.
+    pub fn update_range(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        mut f: impl FnMut(&mut T),
+    ) -> bool {
+        let mut results = Vec::new();
+        let changed = self.map.update_range(offset, len, |data| {
+            f(data);
+            results.push(data.clone());
+        });
+        self.journal.push(JournalOp::UpdateRange { range: Self::range(offset, len), results });
+        changed
+    }
+
+    /// Copies data within the map, see `RangeMap::copy_within`.
+    pub fn copy_within(&mut self, src: K::Offset, dst: K::Offset, len: K::Offset) {
+        self.journal.push(JournalOp::CopyWithin {
+            src: K::from_offset(src),
+            dst: K::from_offset(dst),
+            len: K::from_offset(len),
+        });
+        self.map.copy_within(src, dst, len);
+    }
+
+    /// Merges all equal neighbours, see `RangeMap::merge_adjacent_thorough`.
+    pub fn merge_adjacent_thorough(&mut self) {
+        self.journal.push(JournalOp::MergeAdjacent);
+        self.map.merge_adjacent_thorough();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay() {
+        let mut map = JournaledRangeMap::<i32>::new(24, -1);
+        map.insert_range(6, 4, 42);
+        map.update_range(0, 8, |x| *x = (*x).max(0));
+        map.copy_within(3, 12, 4);
+        map.insert_range(0, 3, 42);
+        assert_eq!(map.journal().len(), 4);
+        assert_eq!(map.journal()[1], JournalOp::UpdateRange { range: 0..8, results: vec![0, 42] });
+        let replayed = map.replay();
+        assert_eq!(format!("{replayed:?}"), format!("{:?}", *map));
+
+        map.merge_adjacent_thorough();
+        assert_eq!(format!("{:?}", map.replay()), format!("{:?}", *map));
+    }
+}
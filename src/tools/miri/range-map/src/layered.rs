@@ -0,0 +1,147 @@
+//! A map made of a shared base map and a stack of overlays on top of it, e.g. to model forked
+//! processes or transactional memory without copying the base. An overlay associates `None` with
+//! everything it does not override; queries are resolved top-down, so the topmost overlay that
+//! associates `Some` with an offset wins, and the base is used if there is none.
+
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::iter;
+use core::ops;
+
+use super::{RangeKey, RangeMap, range_end};
+
+#[derive(Clone, Debug)]
+pub struct LayeredRangeMap<T, K = u64> {
+    /// The base map, shared with all clones of this map.
+    base: Rc<RangeMap<T, K>>,
+    /// The overlays, bottom-most first. They all have the same size as `base`.
+    layers: Vec<RangeMap<Option<T>, K>>,
+}
+
+impl<T, K: RangeKey> LayeredRangeMap<T, K> {
+    /// Creates a new `LayeredRangeMap` on top of the given base map, without any overlays.
+    pub fn new(base: impl Into<Rc<RangeMap<T, K>>>) -> LayeredRangeMap<T, K> {
+        LayeredRangeMap { base: base.into(), layers: Vec::new() }
+    }
+
+    /// Returns the base map.
+    pub fn base(&self) -> &RangeMap<T, K> {
+        &self.base
+    }
+
+    /// Returns the number of overlays.
+    pub fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Pushes a new, empty overlay, which from then on receives all writes. To fork the map,
+    /// clone it (which shares the base) and push a layer onto the clone.
+    pub fn push_layer(&mut self) {
+        self.layers.push(RangeMap::new(self.base.len_bytes(), None));
+    }
+
+    /// Removes the topmost overlay, discarding all writes since it was pushed, and returns it.
+    pub fn pop_layer(&mut self) -> Option<RangeMap<Option<T>, K>> {
+        self.layers.pop()
+    }
+
+    /// Resolves the given offset: returns the data associated with it, together with the end of
+    /// the range starting at `offset` in which that resolution does not change.
+    fn resolve(&self, offset: K) -> (K, &T) {
+        let mut end = self.base.size();
+        for layer in self.layers.iter().rev() {
+            let elem = &layer.v[layer.find_offset(offset)];
+            end = end.min(elem.range.end);
+            if let Some(data) = &elem.data {
+                return (end, data);
+            }
+        }
+        let elem = &self.base.v[self.base.find_offset(offset)];
+        (end.min(elem.range.end), &elem.data)
+    }
+
+    /// Returns the data associated with the given offset.
+    pub fn get(&self, offset: K::Offset) -> &T {
+        self.resolve(K::from_offset(offset)).1
+    }
+
+    /// Provides read-only iteration over everything in the given range. The ranges are clipped
+    /// to the given range, and neighbouring ranges might carry equal data.
+    pub fn iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        let mut pos = K::from_offset(offset);
+        let end = range_end(pos, K::from_offset(len));
+        assert!(end <= self.base.size(), "iterating beyond the bounds of this RangeMap");
+        iter::from_fn(move || {
+            if pos >= end {
+                return None;
+            }
+            let (piece_end, data) = self.resolve(pos);
+            let range = pos..piece_end.min(end);
+            pos = range.end;
+            Some((range, data))
+        })
+    }
+
+    /// Overwrites everything in the given range with `data`. This writes to the topmost overlay,
+    /// or to the base if there are no overlays, copying it first if it is shared.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T)
+    where
+        T: Clone,
+    {
+        match self.layers.last_mut() {
+            Some(layer) => layer.insert_range(offset, len, Some(data)),
+            None => Rc::make_mut(&mut self.base).insert_range(offset, len, data),
+        }
+    }
+
+    /// Returns a single map with all overlays applied to the base.
+    pub fn flatten(&self) -> RangeMap<T, K>
+    where
+        T: Clone + PartialEq,
+    {
+        let mut map = RangeMap::clone(&self.base);
+        for layer in &self.layers {
+            map.apply_overlay(layer);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::sample_map;
+    use super::*;
+
+    #[test]
+    fn overlays() {
+        let base = sample_map();
+        let mut parent = LayeredRangeMap::new(base);
+        let mut child = parent.clone();
+        child.push_layer();
+        child.insert_range(8, 4, 43);
+        child.push_layer();
+        child.insert_range(0, 2, 44);
+        assert!(Rc::ptr_eq(&parent.base, &child.base));
+        assert_eq!(
+            child.iter(1, 12).collect::<Vec<_>>(),
+            vec![(1..2, &44), (2..5, &-1), (5..8, &42), (8..12, &43), (12..13, &-1)]
+        );
+        assert_eq!(*child.get(9), 43);
+        assert_eq!(*parent.get(9), 42);
+        assert_eq!(
+            child.flatten().iter_all().collect::<Vec<_>>(),
+            vec![(0..2, &44), (2..5, &-1), (5..8, &42), (8..12, &43), (12..20, &-1)]
+        );
+
+        assert!(child.pop_layer().is_some());
+        assert_eq!(*child.get(0), -1);
+        // Without overlays, writes go to the base, which is copied since it is shared.
+        parent.insert_range(0, 20, 0);
+        assert!(!Rc::ptr_eq(&parent.base, &child.base));
+        assert_eq!(*child.base().get(0), -1);
+    }
+}
@@ -0,0 +1,97 @@
+//! A `RangeMap` whose initial value is only computed on first access, for when constructing it is
+//! expensive and many maps are never accessed at all (e.g. one map per allocation, most of which
+//! are never read).
+
+use core::cell::{Cell, OnceCell};
+use core::fmt;
+use core::ops;
+
+use super::{RangeKey, RangeMap};
+
+/// A `RangeMap` that is created on first access, see `RangeMap::new_with`. It dereferences to the
+/// map, creating it if needed.
+pub struct LazyRangeMap<T, K = u64, F = fn() -> T> {
+    map: OnceCell<RangeMap<T, K>>,
+    size: K,
+    /// The function computing the initial value; taken once the map is created.
+    init: Cell<Option<F>>,
+}
+
+impl<T, K: RangeKey> RangeMap<T, K> {
+    /// Like `new`, but `init` is only called to compute the initial value once the map is first
+    /// accessed.
+    pub fn new_with<F: FnOnce() -> T>(size: K::Offset, init: F) -> LazyRangeMap<T, K, F> {
+        LazyRangeMap {
+            map: OnceCell::new(),
+            size: K::from_offset(size),
+            init: Cell::new(Some(init)),
+        }
+    }
+}
+
+impl<T, K: RangeKey, F: FnOnce() -> T> LazyRangeMap<T, K, F> {
+    /// Returns whether the map has been created, i.e. whether the initial value was computed.
+    pub fn is_initialized(&self) -> bool {
+        self.map.get().is_some()
+    }
+
+    /// Returns the map, creating it first if needed.
+    pub fn into_inner(self) -> RangeMap<T, K> {
+        let _ = self.force();
+        self.map.into_inner().unwrap()
+    }
+
+    fn force(&self) -> &RangeMap<T, K> {
+        self.map.get_or_init(|| {
+            let init = self.init.take().expect("LazyRangeMap initialization panicked before");
+            RangeMap::new(self.size.to_offset(), init())
+        })
+    }
+}
+
+impl<T, K: RangeKey, F: FnOnce() -> T> ops::Deref for LazyRangeMap<T, K, F> {
+    type Target = RangeMap<T, K>;
+
+    fn deref(&self) -> &RangeMap<T, K> {
+        self.force()
+    }
+}
+
+impl<T, K: RangeKey, F: FnOnce() -> T> ops::DerefMut for LazyRangeMap<T, K, F> {
+    fn deref_mut(&mut self) -> &mut RangeMap<T, K> {
+        let _ = self.force();
+        self.map.get_mut().unwrap()
+    }
+}
+
+/// Prints the map if it has been created, without creating it.
+impl<T: fmt::Debug, K: fmt::Debug, F> fmt::Debug for LazyRangeMap<T, K, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.map.get() {
+            Some(map) => map.fmt(f),
+            None => write!(f, "<uninitialized RangeMap of size {:?}>", self.size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lazy_init() {
+        let calls = Cell::new(0);
+        let mut map = RangeMap::<i32>::new_with(20, || {
+            calls.set(calls.get() + 1);
+            -1
+        });
+        assert!(!map.is_initialized());
+        assert_eq!(format!("{map:?}"), "<uninitialized RangeMap of size 20>");
+        assert_eq!(calls.get(), 0);
+        assert_eq!(*map.get(3), -1);
+        assert!(map.is_initialized());
+        map.insert_range(5, 5, 42);
+        assert_eq!(map.into_inner().num_runs(), 3);
+        assert_eq!(calls.get(), 1);
+    }
+}
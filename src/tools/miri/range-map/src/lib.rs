@@ -0,0 +1,3107 @@
+//! Implements a map from integer indices to data.
+//! Rather than storing data for every index, internally, this maps entire ranges to the data.
+//! To this end, the APIs all work on ranges, not on individual integers. Ranges are split as
+//! necessary (e.g., when [0,5) is first associated with X, and then [1,2) is mutated).
+//! Users must not depend on whether a range is coalesced or not, even though this is observable
+//! via the iteration APIs.
+//!
+//! A map always covers the indices `0..size`, so the indices are relative to whatever object the
+//! map describes (e.g., offsets into an allocation). Relocating that object does not require
+//! touching the map; the base address should be kept separately and added where needed.
+//!
+//! The indices are `u64` by default. Other integer types (including `u128`, for address spaces
+//! wider than 64 bits) can be used via the `RangeKey` trait. Computing the end of a range given by
+//! the user is checked for overflow. Signed types are deliberately not supported: since the map
+//! starts at 0, negative indices would always be out of bounds. To describe something relative to
+//! a base that indices can go below (e.g. a pointer into the middle of a struct), use the lowest
+//! possible index as the map's base instead.
+//!
+//! This only depends on `core`, `alloc` and `smallvec`, so that it can be used in `no_std`
+//! environments.
+
+#![cfg_attr(not(test), no_std)]
+// Configure clippy and other lints
+#![allow(clippy::type_complexity)]
+#![warn(
+    rust_2018_idioms,
+    clippy::cast_possible_wrap, // unsigned -> signed
+    clippy::cast_sign_loss, // signed -> unsigned
+    clippy::cast_lossless,
+    clippy::cast_possible_truncation,
+)]
+
+extern crate alloc;
+
+#[cfg(feature = "la-arena")]
+mod arena;
+mod btree;
+mod builder;
+mod cow;
+mod cursor;
+mod default;
+mod entry;
+#[cfg(any(test, feature = "reference"))]
+mod flat;
+mod granular;
+mod interned;
+mod iter_mut;
+mod journal;
+mod layered;
+mod lazy;
+mod observed;
+mod paged;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod per_alloc;
+mod persistent;
+mod ring;
+#[cfg(feature = "serde")]
+mod serialize;
+mod set;
+mod snapshot;
+mod sparse;
+mod text;
+mod versioned;
+
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::iter;
+use core::mem;
+use core::ops;
+use core::ops::ControlFlow;
+
+use smallvec::SmallVec;
+
+#[cfg(feature = "la-arena")]
+pub use self::arena::ArenaKey;
+pub use self::btree::BTreeRangeMap;
+pub use self::builder::RangeMapBuilder;
+pub use self::cow::CowRangeMap;
+pub use self::cursor::{Cursor, CursorMut};
+pub use self::default::DefaultRangeMap;
+pub use self::entry::Entry;
+#[cfg(feature = "reference")]
+pub use self::flat::FlatMap;
+pub use self::granular::GranularRangeMap;
+pub use self::interned::{DedupRangeMap, HandleTable, InternedRangeMap};
+pub use self::iter_mut::{ByRange, DataMut, IterMut, IterMutShared};
+pub use self::journal::{JournalOp, JournaledRangeMap};
+pub use self::layered::LayeredRangeMap;
+pub use self::lazy::LazyRangeMap;
+pub use self::observed::{ObservedRangeMap, Observer};
+pub use self::paged::PagedRangeMap;
+pub use self::per_alloc::AllocRangeMap;
+pub use self::persistent::PersistentRangeMap;
+pub use self::ring::RingRangeMap;
+pub use self::set::RangeSet;
+pub use self::snapshot::{SnapshotId, SnapshotRangeMap};
+pub use self::sparse::SparseRangeMap;
+pub use self::text::ParseIntervalsError;
+pub use self::versioned::{Version, VersionedRangeMap};
+
+/// The type of the indices of a `RangeMap`.
+///
+/// This is implemented for the unsigned integer types, and can be implemented for newtypes around
+/// them. With the `la-arena` feature, `ArenaKey` keys maps by the typed indices of an
+/// `la_arena::Arena`.
+pub trait RangeKey:
+    Copy + Ord + fmt::Debug + ops::Add<Output = Self> + ops::Sub<Output = Self>
+{
+    /// The type in which offsets and lengths are passed to the `RangeMap` APIs.
+    type Offset: Copy;
+
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn from_offset(offset: Self::Offset) -> Self;
+    fn to_offset(self) -> Self::Offset;
+
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+}
+
+macro_rules! impl_range_key {
+    ($($ty:ty),*) => {$(
+        impl RangeKey for $ty {
+            type Offset = $ty;
+
+            const ZERO: $ty = 0;
+            const ONE: $ty = 1;
+
+            #[inline(always)]
+            fn from_offset(offset: $ty) -> $ty {
+                offset
+            }
+
+            #[inline(always)]
+            fn to_offset(self) -> $ty {
+                self
+            }
+
+            #[inline(always)]
+            fn checked_add(self, rhs: $ty) -> Option<$ty> {
+                <$ty>::checked_add(self, rhs)
+            }
+        }
+    )*};
+}
+
+impl_range_key!(u32, u64, usize, u128);
+
+/// Merges adjacent ranges of a sorted sequence of disjoint ranges, and turns the result into
+/// `(offset, len)` pairs as taken by `RangeMap::iter_mut_multi`.
+fn coalesce<K: RangeKey>(
+    ranges: impl Iterator<Item = ops::Range<K>>,
+) -> Vec<(K::Offset, K::Offset)> {
+    let mut coalesced: Vec<ops::Range<K>> = Vec::new();
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => coalesced.push(range),
+        }
+    }
+    coalesced
+        .into_iter()
+        .map(|range| (range.start.to_offset(), (range.end - range.start).to_offset()))
+        .collect()
+}
+
+/// Computes the end of the range of length `len` starting at `offset`, making sure that it does
+/// not overflow.
+fn range_end<K: RangeKey>(offset: K, len: K) -> K {
+    offset.checked_add(len).unwrap_or_else(|| {
+        panic!("the range of length {len:?} starting at {offset:?} overflows the offset type")
+    })
+}
+
+/// A single run of a `RangeMap`: a range together with the data stored for all of it. See
+/// `RangeMap::as_runs`.
+#[derive(Clone)]
+pub struct Elem<T, K = u64> {
+    /// The range covered by this element; never empty.
+    range: ops::Range<K>,
+    /// The data stored for this element.
+    data: T,
+}
+
+impl<T, K: Copy> Elem<T, K> {
+    /// Returns the range covered by this element.
+    pub fn range(&self) -> ops::Range<K> {
+        self.range.clone()
+    }
+
+    /// Returns the data stored for this element.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+}
+
+/// The error returned by `RangeMap::iter_mut_exact` if the requested range does not start and end
+/// at element boundaries.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MisalignedRange<K = u64> {
+    /// The boundary of the requested range that lies in the middle of an element.
+    pub offset: K,
+}
+
+impl<K: fmt::Debug> fmt::Display for MisalignedRange<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "offset {:?} is not at an element boundary of this RangeMap", self.offset)
+    }
+}
+
+/// The error returned by `RangeMap::compare_and_set` if the requested range does not entirely
+/// map to the expected data.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MismatchAt<K = u64> {
+    /// The first offset in the requested range that maps to different data.
+    pub offset: K,
+}
+
+impl<K: fmt::Debug> fmt::Display for MismatchAt<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "offset {:?} does not map to the expected data", self.offset)
+    }
+}
+
+/// The error returned by `RangeMap::copy_nonoverlapping_from` if the source and destination
+/// ranges overlap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverlapError<K = u64> {
+    /// The source range of the copy.
+    pub src: ops::Range<K>,
+    /// The destination range of the copy.
+    pub dst: ops::Range<K>,
+}
+
+impl<K: fmt::Debug> fmt::Display for OverlapError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "copying from {:?} to {:?}, which overlap", self.src, self.dst)
+    }
+}
+
+/// Statistics about how fragmented a `RangeMap` is, returned by `RangeMap::stats`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RangeMapStats<K = u64> {
+    /// The number of elements.
+    pub runs: usize,
+    /// The average length of an element, rounded down.
+    pub average_run_len: K,
+    /// The length of the longest element.
+    pub largest_run: K,
+    /// The number of bytes allocated on the heap for the elements. This does not include heap
+    /// memory owned by the data itself.
+    pub heap_bytes: usize,
+}
+
+/// The error returned by the `try_` methods of `RangeMap` if the requested range is not within
+/// the bounds of the map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OutOfBounds<K = u64> {
+    /// The start of the requested range.
+    pub offset: K,
+    /// The length of the requested range.
+    pub len: K,
+    /// The size of the map.
+    pub size: K,
+}
+
+impl<K: fmt::Debug> fmt::Display for OutOfBounds<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "range of length {:?} at {:?} is out of bounds of RangeMap of size {:?}",
+            self.len, self.offset, self.size
+        )
+    }
+}
+
+/// The error returned by `RangeMap::try_from_runs` if the runs do not form a valid map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvalidRuns<K = u64> {
+    /// A run does not start where the previous one ends, or at 0 for the first run.
+    NotContiguous { start: K, expected: K },
+    /// A run is empty.
+    Empty(ops::Range<K>),
+}
+
+impl<K: fmt::Debug> fmt::Display for InvalidRuns<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidRuns::NotContiguous { start, expected } =>
+                write!(f, "RangeMap element starts at {start:?} instead of {expected:?}"),
+            InvalidRuns::Empty(range) => write!(f, "RangeMap element {range:?} is empty"),
+        }
+    }
+}
+
+/// How many non-mergeable blocks `iter_mut` tolerates before it stops merging. The value is
+/// chosen via... magic. Benchmarking and magic.
+const DEFAULT_MERGE_BUDGET: usize = 3;
+
+/// The storage of the elements of a `RangeMap`. Most maps are never split, so a single element
+/// is stored inline, without a separate heap allocation. That is also why the storage cannot come
+/// from a custom allocator such as an arena: `SmallVec` does not support allocators, and the
+/// short-lived maps that would benefit from an arena are mostly the ones that never allocate.
+type Elems<T, K> = SmallVec<[Elem<T, K>; 1]>;
+
+/// Replaces the elements in `range` by `new`, like `Vec::splice`. This shifts the elements after
+/// `range` at most once, as long as the size hint of `new` is exact.
+fn splice<T, K>(
+    v: &mut Elems<T, K>,
+    range: ops::Range<usize>,
+    new: impl IntoIterator<Item = Elem<T, K>>,
+) {
+    let mut new = new.into_iter();
+    // Overwrite the old elements in place as far as possible, then remove or insert the rest.
+    for idx in range.clone() {
+        match new.next() {
+            Some(elem) => v[idx] = elem,
+            None => {
+                v.drain(idx..range.end);
+                return;
+            }
+        }
+    }
+    v.insert_many(range.end, new);
+}
+
+#[derive(Clone)]
+pub struct RangeMap<T, K = u64> {
+    v: Elems<T, K>,
+    /// See `set_merge_budget`.
+    merge_budget: usize,
+}
+
+impl<T, K: RangeKey> RangeMap<T, K> {
+    /// Creates a map consisting of the given elements.
+    fn from_elems(v: Elems<T, K>, merge_budget: usize) -> RangeMap<T, K> {
+        RangeMap { v, merge_budget }
+    }
+
+    /// Creates a new `RangeMap` for the given size, and with the given initial value used for
+    /// the entire range.
+    #[inline(always)]
+    pub fn new(size: K::Offset, init: T) -> RangeMap<T, K> {
+        Self::with_capacity(size, init, 0)
+    }
+
+    /// Like `new`, but reserves space for at least `capacity` elements, for when it is known
+    /// in advance how fragmented the map will get.
+    pub fn with_capacity(size: K::Offset, init: T, capacity: usize) -> RangeMap<T, K> {
+        let size = K::from_offset(size);
+        let mut map = RangeMap::from_elems(SmallVec::with_capacity(capacity), DEFAULT_MERGE_BUDGET);
+        if size > K::ZERO {
+            map.v.push(Elem { range: K::ZERO..size, data: init });
+        }
+        map
+    }
+
+    /// Creates a new `RangeMap` from the given ranges and their data. The ranges must be
+    /// non-empty, sorted, and contiguous, starting at 0.
+    pub fn from_sorted_ranges(ranges: impl IntoIterator<Item = (ops::Range<K>, T)>) -> Self {
+        Self::try_from_runs(ranges).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Like `from_sorted_ranges`, but returns an error instead of panicking if the ranges do not
+    /// form a valid map. Together with `into_runs`, this converts between maps and lists of runs.
+    pub fn try_from_runs(
+        runs: impl IntoIterator<Item = (ops::Range<K>, T)>,
+    ) -> Result<Self, InvalidRuns<K>> {
+        let mut v = SmallVec::new();
+        let mut end = K::ZERO;
+        for (range, data) in runs {
+            if range.start != end {
+                return Err(InvalidRuns::NotContiguous { start: range.start, expected: end });
+            }
+            if range.start >= range.end {
+                return Err(InvalidRuns::Empty(range));
+            }
+            end = range.end;
+            v.push(Elem { range, data });
+        }
+        Ok(RangeMap::from_elems(v, DEFAULT_MERGE_BUDGET))
+    }
+
+    /// Converts this map into the list of its elements' ranges and data.
+    pub fn into_runs(self) -> Vec<(ops::Range<K>, T)> {
+        self.v.into_iter().map(|elem| (elem.range, elem.data)).collect()
+    }
+
+    /// Creates a new `RangeMap` for the given size from the given ranges and their data. The
+    /// ranges must be non-empty, sorted and non-overlapping; gaps between them are associated
+    /// with `default`.
+    pub fn from_sorted_ranges_with_default(
+        size: K::Offset,
+        ranges: impl IntoIterator<Item = (ops::Range<K>, T)>,
+        default: T,
+    ) -> Self
+    where
+        T: Clone,
+    {
+        let size = K::from_offset(size);
+        let mut v = SmallVec::new();
+        let mut end = K::ZERO;
+        for (range, data) in ranges {
+            assert!(range.start >= end, "ranges of a RangeMap must be sorted and non-overlapping");
+            assert!(range.start < range.end, "ranges of a RangeMap must not be empty");
+            if range.start > end {
+                v.push(Elem { range: end..range.start, data: default.clone() });
+            }
+            end = range.end;
+            v.push(Elem { range, data });
+        }
+        assert!(end <= size, "ranges of a RangeMap must not exceed its size");
+        if size > end {
+            v.push(Elem { range: end..size, data: default });
+        }
+        RangeMap::from_elems(v, DEFAULT_MERGE_BUDGET)
+    }
+
+    /// The end of the range covered by this map.
+    fn size(&self) -> K {
+        self.v.last().map_or(K::ZERO, |elem| elem.range.end)
+    }
+
+    /// Returns the size of the range covered by this map.
+    pub fn len_bytes(&self) -> K::Offset {
+        self.size().to_offset()
+    }
+
+    /// Returns the range covered by this map. This always starts at 0.
+    pub fn bounds(&self) -> ops::Range<K> {
+        K::ZERO..self.size()
+    }
+
+    /// Returns whether this map covers an empty range.
+    pub fn is_empty(&self) -> bool {
+        self.v.is_empty()
+    }
+
+    /// Returns the number of elements this map currently consists of, i.e., how fragmented it is.
+    /// This depends on how exactly the map is split, so it should only be used for diagnostics.
+    pub fn num_runs(&self) -> usize {
+        self.v.len()
+    }
+
+    /// Returns statistics about how fragmented this map is. Like `num_runs`, this depends on how
+    /// exactly the map is split, so it should only be used for diagnostics.
+    pub fn stats(&self) -> RangeMapStats<K>
+    where
+        K: ops::Div<Output = K> + TryFrom<usize>,
+    {
+        let runs = self.v.len();
+        let average_run_len = match K::try_from(runs) {
+            Ok(runs) if runs > K::ZERO => self.size() / runs,
+            _ => K::ZERO,
+        };
+        let largest_run =
+            self.v.iter().map(|elem| elem.range.end - elem.range.start).max().unwrap_or(K::ZERO);
+        let heap_bytes = self.heap_size_of(|_| 0);
+        RangeMapStats { runs, average_run_len, largest_run, heap_bytes }
+    }
+
+    /// Returns the number of bytes this map has allocated on the heap, plus whatever `value_size`
+    /// reports for the data of every element, e.g. the heap memory owned by that data. Shared
+    /// data is counted once per element it is stored in.
+    pub fn heap_size_of(&self, value_size: impl Fn(&T) -> usize) -> usize {
+        let elems =
+            if self.v.spilled() { self.v.capacity() * mem::size_of::<Elem<T, K>>() } else { 0 };
+        self.v.iter().fold(elems, |total, elem| total + value_size(&elem.data))
+    }
+
+    /// Sets how many non-mergeable blocks `iter_mut` and friends tolerate before they stop
+    /// opportunistically merging equal neighbours. `0` disables merging entirely, `usize::MAX`
+    /// merges everything in the iterated range. Either way, `merge_adjacent_thorough` can be used
+    /// to merge the entire map at once.
+    pub fn set_merge_budget(&mut self, budget: usize) {
+        self.merge_budget = budget;
+    }
+
+    /// Returns the first element of this map, or `None` if the map is empty. Since elements are
+    /// not necessarily maximal, neighbouring elements might carry equal data.
+    pub fn first_run(&self) -> Option<(ops::Range<K>, &T)> {
+        self.v.first().map(|elem| (elem.range.clone(), &elem.data))
+    }
+
+    /// Returns the last element of this map, or `None` if the map is empty. Since elements are
+    /// not necessarily maximal, neighbouring elements might carry equal data.
+    pub fn last_run(&self) -> Option<(ops::Range<K>, &T)> {
+        self.v.last().map(|elem| (elem.range.clone(), &elem.data))
+    }
+
+    /// Like `first_run`, but provides mutable access to the data of the entire element.
+    pub fn first_run_mut(&mut self) -> Option<(ops::Range<K>, &mut T)> {
+        self.v.first_mut().map(|elem| (elem.range.clone(), &mut elem.data))
+    }
+
+    /// Like `last_run`, but provides mutable access to the data of the entire element.
+    pub fn last_run_mut(&mut self) -> Option<(ops::Range<K>, &mut T)> {
+        self.v.last_mut().map(|elem| (elem.range.clone(), &mut elem.data))
+    }
+
+    /// Checks the internal invariants of this map: the elements are non-empty and contiguous,
+    /// and the first one starts at 0. Panics if any of them is violated.
+    /// This takes linear time, so it is meant for tests and debugging.
+    pub fn validate(&self) {
+        let mut end = K::ZERO;
+        for (idx, elem) in self.v.iter().enumerate() {
+            assert!(
+                elem.range.start == end,
+                "RangeMap element {idx} starts at {:?}, but the previous element ends at {end:?}",
+                elem.range.start
+            );
+            assert!(
+                elem.range.start < elem.range.end,
+                "RangeMap element {idx} ({:?}) is empty",
+                elem.range
+            );
+            end = elem.range.end;
+        }
+    }
+
+    /// Finds the index containing the given offset.
+    fn find_offset(&self, offset: K) -> usize {
+        // We do a binary search.
+        let mut left = 0usize; // inclusive
+        let mut right = self.v.len(); // exclusive
+        loop {
+            // This is checked even in release builds: an out-of-bounds offset is a bug in the
+            // caller, and we want a clear panic rather than some bogus index.
+            assert!(left < right, "RangeMap: offset {offset:?} is out-of-bounds");
+            let candidate = left.checked_add(right).unwrap() / 2;
+            let elem = &self.v[candidate];
+            if offset < elem.range.start {
+                // We are too far right (offset is further left).
+                debug_assert!(candidate < right); // we are making progress
+                right = candidate;
+            } else if offset >= elem.range.end {
+                // We are too far left (offset is further right).
+                debug_assert!(candidate >= left); // we are making progress
+                left = candidate + 1;
+            } else {
+                // This is it!
+                return candidate;
+            }
+        }
+    }
+
+    /// Finds the index containing the given offset, or `None` if the offset is out-of-bounds.
+    fn try_find_offset(&self, offset: K) -> Option<usize> {
+        if offset >= self.v.last()?.range.end {
+            return None;
+        }
+        Some(self.find_offset(offset))
+    }
+
+    /// Returns the data associated with the given offset, or `None` if the offset is
+    /// out-of-bounds.
+    pub fn try_get(&self, offset: K::Offset) -> Option<&T> {
+        let idx = self.try_find_offset(K::from_offset(offset))?;
+        Some(&self.v[idx].data)
+    }
+
+    /// Returns the data associated with the given offset.
+    pub fn get(&self, offset: K::Offset) -> &T {
+        self.try_get(offset).expect("getting an offset beyond the bounds of this RangeMap")
+    }
+
+    /// Provides mutable access to the data associated with the given offset, like `iter_mut` for
+    /// a range of length 1: the element containing the offset is split so that the mutation only
+    /// affects that offset, and merged with its neighbours again once the guard is dropped.
+    pub fn get_mut(&mut self, offset: K::Offset) -> DataMut<'_, T, K>
+    where
+        T: Clone + PartialEq,
+    {
+        let start = K::from_offset(offset);
+        assert!(start < self.size(), "getting an offset beyond the bounds of this RangeMap");
+        let idx = self.split_range(offset, K::ONE.to_offset()).start;
+        DataMut::new(self, idx)
+    }
+
+    /// Returns the largest range around the given offset that is associated with equal data,
+    /// together with that data. This does not depend on how the map is split internally.
+    pub fn range_containing(&self, offset: K::Offset) -> (ops::Range<K>, &T)
+    where
+        T: PartialEq,
+    {
+        let idx = self
+            .try_find_offset(K::from_offset(offset))
+            .expect("querying an offset beyond the bounds of this RangeMap");
+        let data = &self.v[idx].data;
+        // Extend the range across all neighbours with equal data.
+        let first = self.v[..idx].iter().rev().take_while(|elem| elem.data == *data).count();
+        let last = self.v[idx + 1..].iter().take_while(|elem| elem.data == *data).count();
+        (self.v[idx - first].range.start..self.v[idx + last].range.end, data)
+    }
+
+    /// Provides read-only iteration over everything in the given range. This does
+    /// *not* split items if they overlap with the edges. Do not use this to mutate
+    /// through interior mutability.
+    ///
+    /// The iterator also provides the range of the given element.
+    /// How exactly the ranges are split can differ even for otherwise identical
+    /// maps, so user-visible behavior should never depend on the exact range.
+    pub fn iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        self.elems_in(K::from_offset(offset), K::from_offset(len))
+            .iter()
+            .map(|elem| (elem.range.clone(), &elem.data))
+    }
+
+    /// Like `iter`, but yields the elements in reverse order, starting at the end of the range.
+    pub fn iter_rev(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        self.elems_in(K::from_offset(offset), K::from_offset(len))
+            .iter()
+            .rev()
+            .map(|elem| (elem.range.clone(), &elem.data))
+    }
+
+    /// Returns an error if the given range is not within the bounds of this map.
+    fn check_bounds(&self, offset: K::Offset, len: K::Offset) -> Result<(), OutOfBounds<K>> {
+        let offset = K::from_offset(offset);
+        let len = K::from_offset(len);
+        let size = self.size();
+        match offset.checked_add(len) {
+            Some(end) if end <= size => Ok(()),
+            _ => Err(OutOfBounds { offset, len, size }),
+        }
+    }
+
+    /// Converts `range` into the `(offset, len)` pair taken by most APIs. Unbounded ends are
+    /// resolved to the bounds of this map.
+    fn offset_len(&self, range: impl ops::RangeBounds<K::Offset>) -> (K::Offset, K::Offset) {
+        let start = match range.start_bound() {
+            ops::Bound::Included(&start) => K::from_offset(start),
+            ops::Bound::Excluded(&start) => range_end(K::from_offset(start), K::ONE),
+            ops::Bound::Unbounded => K::ZERO,
+        };
+        let end = match range.end_bound() {
+            ops::Bound::Included(&end) => range_end(K::from_offset(end), K::ONE),
+            ops::Bound::Excluded(&end) => K::from_offset(end),
+            ops::Bound::Unbounded => self.size(),
+        };
+        assert!(start <= end, "range passed to RangeMap starts after its end");
+        (start.to_offset(), (end - start).to_offset())
+    }
+
+    /// Like `iter`, but takes the range as a Rust range, which may be inclusive or open-ended.
+    pub fn iter_range(
+        &self,
+        range: impl ops::RangeBounds<K::Offset>,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        let (offset, len) = self.offset_len(range);
+        self.iter(offset, len)
+    }
+
+    /// Like `iter`, but returns an error instead of panicking if the range is out of bounds.
+    pub fn try_iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> Result<impl Iterator<Item = (ops::Range<K>, &T)>, OutOfBounds<K>> {
+        self.check_bounds(offset, len)?;
+        Ok(self.iter(offset, len))
+    }
+
+    /// Returns the elements overlapping the given range, which must be in bounds.
+    fn elems_in(&self, offset: K, len: K) -> &[Elem<T, K>] {
+        // The first offset that is not included any more.
+        let end = range_end(offset, len);
+        assert!(end <= self.size(), "iterating beyond the bounds of this RangeMap");
+        if len == K::ZERO {
+            // We just need any empty slice. We don't even want to
+            // yield the element that surrounds this position.
+            return &[];
+        }
+        &self.v[self.find_offset(offset)..=self.find_offset(end - K::ONE)]
+    }
+
+    /// Like `iter`, but the provided ranges are clipped to the given range. This means the
+    /// ranges do not depend on how the map is split outside the queried range, so they can be
+    /// used directly e.g. for diagnostics.
+    pub fn iter_clipped(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        let start = K::from_offset(offset);
+        let end = range_end(start, K::from_offset(len));
+        self.iter(offset, len)
+            .map(move |(range, data)| (range.start.max(start)..range.end.min(end), data))
+    }
+
+    /// Provides read-only iteration over several windows, given as `(offset, len)` pairs, in a
+    /// single pass over the map. The windows must be sorted and disjoint. Yields the index of the
+    /// window along with the data and its range, clipped to the window.
+    pub fn iter_disjoint<'a>(
+        &'a self,
+        windows: &'a [(K::Offset, K::Offset)],
+    ) -> impl Iterator<Item = (usize, ops::Range<K>, &'a T)> + 'a {
+        let size = self.size();
+        let mut prev_end = K::ZERO;
+        // The index of the first element that might overlap the next window.
+        let mut idx = 0;
+        windows.iter().enumerate().flat_map(move |(window, &(offset, len))| {
+            let start = K::from_offset(offset);
+            let end = range_end(start, K::from_offset(len));
+            assert!(
+                start >= prev_end,
+                "windows passed to iter_disjoint must be sorted and disjoint"
+            );
+            assert!(end <= size, "iterating beyond the bounds of this RangeMap");
+            prev_end = end;
+            let elems = if start == end {
+                &[][..]
+            } else {
+                // Only search if the window does not start in the element we are at, which is
+                // usually the case for adjacent windows.
+                if self.v[idx].range.end <= start {
+                    idx += self.v[idx..].partition_point(|elem| elem.range.end <= start);
+                }
+                let len = self.v[idx..].iter().take_while(|elem| elem.range.start < end).count();
+                let elems = &self.v[idx..idx + len];
+                // The last element might also overlap the next window.
+                idx += len - 1;
+                elems
+            };
+            elems.iter().map(move |elem| {
+                (window, elem.range.start.max(start)..elem.range.end.min(end), &elem.data)
+            })
+        })
+    }
+
+    /// Calls `f` on everything in the given range, like `iter_clipped(..).try_for_each(..)`, and
+    /// stops as soon as `f` returns `ControlFlow::Break`.
+    pub fn try_for_each<B>(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+        mut f: impl FnMut(ops::Range<K>, &T) -> ControlFlow<B>,
+    ) -> ControlFlow<B> {
+        self.iter_clipped(offset, len).try_for_each(|(range, data)| f(range, data))
+    }
+
+    /// Splits the given range into chunks at multiples of `chunk`, and provides read-only
+    /// iteration over each of them, like `iter_clipped`. The first and last chunk are clipped to
+    /// the given range, so only the chunks in between are exactly `chunk` long.
+    pub fn iter_chunks(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+        chunk: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, impl Iterator<Item = (ops::Range<K>, &T)>)>
+    where
+        K: ops::Rem<Output = K>,
+    {
+        let start = K::from_offset(offset);
+        let end = range_end(start, K::from_offset(len));
+        let chunk = K::from_offset(chunk);
+        assert!(chunk > K::ZERO, "the chunk size must not be zero");
+        let mut elems = self.elems_in(start, end - start);
+        let mut chunk_start = start;
+        iter::from_fn(move || {
+            if chunk_start >= end {
+                return None;
+            }
+            // If this overflows, the chunk extends beyond the end anyway.
+            let chunk_end = (chunk_start - chunk_start % chunk)
+                .checked_add(chunk)
+                .map_or(end, |chunk_end| chunk_end.min(end));
+            let range = chunk_start..chunk_end;
+            chunk_start = chunk_end;
+            // Skip the elements that ended in the previous chunk. They are sorted, so what is
+            // left starts with the elements overlapping this chunk.
+            elems = &elems[elems.partition_point(|elem| elem.range.end <= range.start)..];
+            let runs = elems.iter().take_while(move |elem| elem.range.start < chunk_end).map(
+                move |elem| {
+                    (elem.range.start.max(range.start)..elem.range.end.min(chunk_end), &elem.data)
+                },
+            );
+            Some((range.clone(), runs))
+        })
+    }
+
+    /// Returns the number of maximal runs of equal data in the given range. Unlike the number of
+    /// elements, this does not depend on how the map is split internally.
+    pub fn count_runs(&self, offset: K::Offset, len: K::Offset) -> usize
+    where
+        T: PartialEq,
+    {
+        let elems = self.elems_in(K::from_offset(offset), K::from_offset(len));
+        elems.first().map_or(0, |_| 1)
+            + elems.windows(2).filter(|pair| pair[0].data != pair[1].data).count()
+    }
+
+    /// Returns the number of distinct values in the given range. This does not allocate, but
+    /// takes quadratic time in the number of elements in the range.
+    pub fn count_distinct(&self, offset: K::Offset, len: K::Offset) -> usize
+    where
+        T: PartialEq,
+    {
+        let elems = self.elems_in(K::from_offset(offset), K::from_offset(len));
+        elems
+            .iter()
+            .enumerate()
+            .filter(|&(idx, elem)| elems[..idx].iter().all(|prev| prev.data != elem.data))
+            .count()
+    }
+
+    /// Returns the first offset in the given range whose data does not satisfy `pred`, or the end
+    /// of the range if all of it does. Like `slice::partition_point`, this assumes that the range
+    /// is partitioned by `pred`, i.e., everything satisfying it comes first, and does a binary
+    /// search over the elements.
+    pub fn partition_point(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+        mut pred: impl FnMut(&T) -> bool,
+    ) -> K::Offset {
+        let start = K::from_offset(offset);
+        let len = K::from_offset(len);
+        let elems = self.elems_in(start, len);
+        match elems.get(elems.partition_point(|elem| pred(&elem.data))) {
+            Some(elem) => elem.range.start.max(start).to_offset(),
+            None => range_end(start, len).to_offset(),
+        }
+    }
+
+    /// Folds `f` over everything in the given range, like `iter_clipped(..).fold(..)`. Since the
+    /// ranges are clipped, `f` can use their length e.g. to count how many bytes in the range
+    /// satisfy some property.
+    pub fn fold<B>(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+        init: B,
+        mut f: impl FnMut(B, ops::Range<K>, &T) -> B,
+    ) -> B {
+        self.iter_clipped(offset, len).fold(init, |acc, (range, data)| f(acc, range, data))
+    }
+
+    /// Returns the data associated with the given range if the entire range is associated with
+    /// equal data, and `None` otherwise (or if the range is empty). This never splits anything.
+    pub fn is_uniform(&self, offset: K::Offset, len: K::Offset) -> Option<&T>
+    where
+        T: PartialEq,
+    {
+        let mut iter = self.iter(offset, len);
+        let (_, data) = iter.next()?;
+        iter.all(|(_, other)| other == data).then_some(data)
+    }
+
+    /// Finds the lowest offset that is a multiple of `align`, such that the `len` bytes starting
+    /// there are all associated with data satisfying `pred`, e.g. to find a free range in a map
+    /// describing an address space. Returns `None` if there is no such offset.
+    pub fn find_range_with(
+        &self,
+        mut pred: impl FnMut(&T) -> bool,
+        len: K::Offset,
+        align: K::Offset,
+    ) -> Option<K::Offset>
+    where
+        K: ops::Rem<Output = K>,
+    {
+        let len = K::from_offset(len);
+        let align = K::from_offset(align);
+        assert!(align > K::ZERO, "the alignment must not be zero");
+        // The aligned start of the current stretch of elements satisfying `pred`, if any.
+        let mut start = None;
+        for elem in &self.v {
+            if !pred(&elem.data) {
+                start = None;
+                continue;
+            }
+            let start = match start {
+                Some(start) => start,
+                None => {
+                    let misalignment = elem.range.start % align;
+                    let aligned = if misalignment == K::ZERO {
+                        elem.range.start
+                    } else {
+                        // If this overflows, there is nothing left to find.
+                        elem.range.start.checked_add(align - misalignment)?
+                    };
+                    *start.insert(aligned)
+                }
+            };
+            if start.checked_add(len).is_some_and(|end| end <= elem.range.end) {
+                return Some(start.to_offset());
+            }
+        }
+        None
+    }
+
+    /// Returns the bitwise OR of the data associated with everything in the given range, or
+    /// `None` if the range is empty. For flag-like data, this says which flags are set anywhere
+    /// in the range.
+    pub fn any_in_range(&self, offset: K::Offset, len: K::Offset) -> Option<T>
+    where
+        T: Copy + ops::BitOr<Output = T>,
+    {
+        self.elems_in(K::from_offset(offset), K::from_offset(len))
+            .iter()
+            .map(|elem| elem.data)
+            .reduce(|a, b| a | b)
+    }
+
+    /// Returns the bitwise AND of the data associated with everything in the given range, or
+    /// `None` if the range is empty. For flag-like data, this says which flags are set everywhere
+    /// in the range.
+    pub fn all_in_range(&self, offset: K::Offset, len: K::Offset) -> Option<T>
+    where
+        T: Copy + ops::BitAnd<Output = T>,
+    {
+        self.elems_in(K::from_offset(offset), K::from_offset(len))
+            .iter()
+            .map(|elem| elem.data)
+            .reduce(|a, b| a & b)
+    }
+
+    /// Provides mutable iteration over all elements.
+    /// The iterator also provides the range of the given element.
+    /// How exactly the ranges are split can differ even for otherwise identical
+    /// maps, so user-visible behavior should never depend on the exact range.
+    pub fn iter_mut_all(
+        &mut self,
+    ) -> impl ExactSizeIterator<Item = (ops::Range<K>, &mut T)> + DoubleEndedIterator {
+        self.v.iter_mut().map(|elem| (elem.range.clone(), &mut elem.data))
+    }
+
+    /// Returns the elements of this map, sorted by offset. This borrows the representation
+    /// directly, so walking it neither allocates nor clones any data, which matters when
+    /// serializing huge maps. Like `iter_all`, this depends on how exactly the map is split.
+    pub fn as_runs(&self) -> &[Elem<T, K>] {
+        &self.v
+    }
+
+    /// Provides iteration over all elements.
+    /// The iterator also provides the range of the given element.
+    /// How exactly the ranges are split can differ even for otherwise identical
+    /// maps, so user-visible behavior should never depend on the exact range.
+    pub fn iter_all(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (ops::Range<K>, &T)> + DoubleEndedIterator {
+        self.v.iter().map(|elem| (elem.range.clone(), &elem.data))
+    }
+
+    /// Provides iteration over all elements whose data differs from `default`, skipping the rest.
+    /// Like `iter_all`, this depends on how exactly the map is split.
+    pub fn iter_nondefault<'a>(
+        &'a self,
+        default: &'a T,
+    ) -> impl DoubleEndedIterator<Item = (ops::Range<K>, &'a T)> + 'a
+    where
+        T: PartialEq,
+    {
+        self.iter_all().filter(move |(_, data)| *data != default)
+    }
+
+    /// Provides iteration over the maximal ranges in which all data is equal, i.e., neighbouring
+    /// elements with equal data are yielded together. Unlike `iter_all`, this does not depend on
+    /// how exactly the map is split.
+    pub fn iter_maximal(&self) -> impl Iterator<Item = (ops::Range<K>, &T)>
+    where
+        T: PartialEq,
+    {
+        let mut elems = self.v.iter().peekable();
+        iter::from_fn(move || {
+            let elem = elems.next()?;
+            let mut range = elem.range.clone();
+            while let Some(next) = elems.next_if(|next| next.data == elem.data) {
+                range.end = next.range.end;
+            }
+            Some((range, &elem.data))
+        })
+    }
+
+    /// Provides iteration over the ranges of all elements, without their data.
+    /// Like for `iter_all`, the exact ranges depend on how the map is split.
+    pub fn ranges(
+        &self,
+    ) -> impl ExactSizeIterator<Item = ops::Range<K>> + DoubleEndedIterator + '_ {
+        self.v.iter().map(|elem| elem.range.clone())
+    }
+
+    /// Provides iteration over the maximal ranges in which all data is equal, without the data.
+    /// See `iter_maximal`.
+    pub fn maximal_ranges(&self) -> impl Iterator<Item = ops::Range<K>> + '_
+    where
+        T: PartialEq,
+    {
+        self.iter_maximal().map(|(range, _)| range)
+    }
+
+    /// Provides mutable iteration over everything in the given range. As a side-effect,
+    /// this will split entries in the map that are only partially hit by the given range,
+    /// to make sure that when they are mutated, the effect is constrained to the given range.
+    /// Moreover, this will opportunistically merge neighbouring equal blocks. That happens in a
+    /// separate pass over the mutated range, after the mutation: the elements are accessed
+    /// through the returned guard, and merged once it is dropped.
+    ///
+    /// The iteration also provides the range of the given element. Since elements are split at
+    /// the edges, these ranges always lie within the given range and together cover all of it.
+    /// How exactly the ranges are split (both prior to and resulting from the execution of this
+    /// function) can differ even for otherwise identical maps,
+    /// so user-visible behavior should never depend on the exact range.
+    pub fn iter_mut(&mut self, offset: K::Offset, len: K::Offset) -> IterMut<'_, T, K>
+    where
+        T: Clone + PartialEq,
+    {
+        let range = self.split_range(offset, len);
+        IterMut::new(self, smallvec::smallvec![range])
+    }
+
+    /// Provides mutable iteration over everything in the given range, like `iter_mut`, but
+    /// without splitting or merging anything. This only works if the range starts and ends at
+    /// element boundaries, and returns an error otherwise. In exchange, this does not need
+    /// `T: Clone`.
+    pub fn iter_mut_exact(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> Result<impl Iterator<Item = (ops::Range<K>, &mut T)>, MisalignedRange<K>> {
+        let offset = K::from_offset(offset);
+        let end = range_end(offset, K::from_offset(len));
+        assert!(end <= self.size(), "iterating beyond the bounds of this RangeMap");
+        let elems = if offset == end {
+            0..0
+        } else {
+            let first_idx = self.find_offset(offset);
+            if self.v[first_idx].range.start != offset {
+                return Err(MisalignedRange { offset });
+            }
+            let last_idx = self.find_offset(end - K::ONE);
+            if self.v[last_idx].range.end != end {
+                return Err(MisalignedRange { offset: end });
+            }
+            first_idx..last_idx + 1
+        };
+        Ok(self.v[elems].iter_mut().map(|elem| (elem.range.clone(), &mut elem.data)))
+    }
+
+    /// Like `iter_mut`, but returns an error instead of panicking if the range is out of bounds.
+    pub fn try_iter_mut(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> Result<IterMut<'_, T, K>, OutOfBounds<K>>
+    where
+        T: Clone + PartialEq,
+    {
+        self.check_bounds(offset, len)?;
+        Ok(self.iter_mut(offset, len))
+    }
+
+    /// Like `iter_mut`, but takes the range as a Rust range, which may be inclusive or
+    /// open-ended.
+    pub fn iter_mut_range(&mut self, range: impl ops::RangeBounds<K::Offset>) -> IterMut<'_, T, K>
+    where
+        T: Clone + PartialEq,
+    {
+        let (offset, len) = self.offset_len(range);
+        self.iter_mut(offset, len)
+    }
+
+    /// Like `iter_mut`, but for several ranges at once, given as `(offset, len)` pairs. The ranges
+    /// must be sorted and disjoint. All splitting is done before the first item is yielded; use
+    /// `IterMut::by_range` to get one iterator per range, in the given order. Once the guard is
+    /// dropped, everything between the first and the last range gets merged.
+    pub fn iter_mut_multi(&mut self, ranges: &[(K::Offset, K::Offset)]) -> IterMut<'_, T, K>
+    where
+        T: Clone + PartialEq,
+    {
+        let mut prev_end = K::ZERO;
+        let idxs = ranges
+            .iter()
+            .map(|&(offset, len)| {
+                let start = K::from_offset(offset);
+                assert!(
+                    start >= prev_end,
+                    "ranges passed to iter_mut_multi must be sorted and disjoint"
+                );
+                prev_end = range_end(start, K::from_offset(len));
+                // Later ranges only touch elements after the ones covering this range, so the
+                // indices we get here stay valid.
+                self.split_range(offset, len)
+            })
+            .collect();
+        IterMut::new(self, idxs)
+    }
+
+    /// Like `iter_mut`, but only provides access to the parts of the given range where `mask` is
+    /// `true`. Elements are split at the boundaries of the mask as needed.
+    pub fn iter_mut_masked(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        mask: &RangeMap<bool, K>,
+    ) -> IterMut<'_, T, K>
+    where
+        T: Clone + PartialEq,
+    {
+        let ranges = coalesce(
+            mask.iter_clipped(offset, len).filter(|&(_, &masked)| masked).map(|(range, _)| range),
+        );
+        self.iter_mut_multi(&ranges)
+    }
+
+    /// Like `iter_mut`, but only provides access to the elements whose data satisfies `pred`.
+    /// Elements that do not satisfy it are neither visited nor split, so e.g. marking everything
+    /// uninitialized in a range as initialized does not fragment the parts that already are.
+    pub fn iter_mut_where(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        pred: impl Fn(&T) -> bool,
+    ) -> IterMut<'_, T, K>
+    where
+        T: Clone + PartialEq,
+    {
+        let ranges = coalesce(
+            self.iter_clipped(offset, len).filter(|(_, data)| pred(data)).map(|(range, _)| range),
+        );
+        self.iter_mut_multi(&ranges)
+    }
+
+    /// Like `iter_mut`, but uses `equiv` instead of `PartialEq` to decide which neighbouring
+    /// blocks can be merged. When blocks are merged, the data of the first one is kept.
+    pub fn iter_mut_merging_by(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        equiv: impl FnMut(&T, &T) -> bool,
+    ) -> impl Iterator<Item = (ops::Range<K>, &mut T)>
+    where
+        T: Clone,
+    {
+        let range = self.split_range(offset, len);
+        let range = self.merge_window(range, equiv);
+        self.v[range].iter_mut().map(|elem| (elem.range.clone(), &mut elem.data))
+    }
+
+    /// Splits the elements at the edges of the given range, and returns the indices of the
+    /// elements covering exactly that range.
+    fn split_range(&mut self, offset: K::Offset, len: K::Offset) -> ops::Range<usize>
+    where
+        T: Clone,
+    {
+        let offset = K::from_offset(offset);
+        let end = range_end(offset, K::from_offset(len));
+        if offset == end {
+            // We just need any empty iterator. We don't even want to
+            // yield the element that surrounds this position, nor do
+            // any splitting.
+            return 0..0;
+        }
+        assert!(end <= self.size(), "iterating beyond the bounds of this RangeMap");
+        // `last` is inclusive.
+        let first = self.find_offset(offset);
+        let last = self.find_offset(end - K::ONE);
+        // Cut off the parts of the outermost elements that stick out of the range.
+        let head = (self.v[first].range.start < offset).then(|| {
+            let elem = &mut self.v[first];
+            let head = Elem { range: elem.range.start..offset, data: elem.data.clone() };
+            elem.range.start = offset;
+            head
+        });
+        let tail = (self.v[last].range.end > end).then(|| {
+            let elem = &mut self.v[last];
+            let tail = Elem { range: end..elem.range.end, data: elem.data.clone() };
+            elem.range.end = end;
+            tail
+        });
+        // Insert the cut-off parts in a single splice, so that the tail of the vector only gets
+        // shifted once. The head ends up behind the range, so rotate it into place; that only
+        // moves elements we are about to iterate over anyway.
+        let has_head = head.is_some();
+        splice(&mut self.v, last + 1..last + 1, head.into_iter().chain(tail));
+        if has_head {
+            self.v[first..=last + 1].rotate_right(1);
+        }
+        first + usize::from(has_head)..last + 1 + usize::from(has_head)
+    }
+
+    /// Merges equivalent neighbours among the elements in `window`, and returns the indices of
+    /// the resulting elements. When elements are merged, the data of the first one is kept.
+    fn merge_window(
+        &mut self,
+        window: ops::Range<usize>,
+        mut equiv: impl FnMut(&T, &T) -> bool,
+    ) -> ops::Range<usize> {
+        if window.is_empty() {
+            return window;
+        }
+        // Compact the window in place: `w` is the last element of the compacted prefix, and
+        // everything after it in the window is dead once we are done.
+        // Once we see too many non-mergeable blocks, we stop merging, to avoid spending excessive
+        // amounts of time on it.
+        let mut successful_merge_count = self.merge_budget;
+        let mut block_merged = false;
+        let mut w = window.start;
+        for r in window.start + 1..window.end {
+            if successful_merge_count > 0 && equiv(&self.v[w].data, &self.v[r].data) {
+                self.v[w].range.end = self.v[r].range.end;
+                successful_merge_count = successful_merge_count.saturating_add(1);
+                block_merged = true;
+            } else {
+                if !block_merged {
+                    successful_merge_count = successful_merge_count.saturating_sub(1);
+                }
+                block_merged = false;
+                w += 1;
+                self.v.swap(w, r);
+            }
+        }
+        self.v.drain(w + 1..window.end);
+        window.start..w + 1
+    }
+
+    /// Calls `f` on everything in the given range, like `iter_mut`, but only splits elements when
+    /// needed. Elements that stick out of the range are passed to `f` as a copy, which is only
+    /// written back (splitting the element) if `f` returns `true` to indicate that it changed the
+    /// value. The provided ranges are clipped to the given range.
+    pub fn for_each_mut(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        mut f: impl FnMut(ops::Range<K>, &mut T) -> bool,
+    ) where
+        T: Clone,
+    {
+        let offset = K::from_offset(offset);
+        let end = range_end(offset, K::from_offset(len));
+        if offset == end {
+            return;
+        }
+        assert!(end <= self.size(), "iterating beyond the bounds of this RangeMap");
+        let mut idx = self.find_offset(offset);
+        loop {
+            let elem = &mut self.v[idx];
+            let range = elem.range.start.max(offset)..elem.range.end.min(end);
+            if range == elem.range {
+                f(range.clone(), &mut elem.data);
+                idx += 1;
+            } else {
+                let mut data = elem.data.clone();
+                if f(range.clone(), &mut data) {
+                    self.splice_range(
+                        range.clone(),
+                        iter::once(Elem { range: range.clone(), data }),
+                    );
+                    // Splitting moved things around; find the element after the one we wrote.
+                    idx = self.find_offset(range.start) + 1;
+                } else {
+                    idx += 1;
+                }
+            }
+            if range.end == end {
+                break;
+            }
+        }
+    }
+
+    /// Applies `f` to everything in the given range, and returns whether that changed any value.
+    /// Like `for_each_mut`, elements are only split if their value actually changes.
+    pub fn update_range(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        mut f: impl FnMut(&mut T),
+    ) -> bool
+    where
+        T: Clone + PartialEq,
+    {
+        let mut changed = false;
+        self.for_each_mut(offset, len, |_, data| {
+            let old = data.clone();
+            f(data);
+            let elem_changed = *data != old;
+            changed |= elem_changed;
+            elem_changed
+        });
+        changed
+    }
+
+    /// Resets everything in the given range that does not satisfy `pred` to `default`, and
+    /// merges equal neighbours in (and directly around) the range along the way, subject to the
+    /// merge budget. Returns whether anything was reset.
+    pub fn retain(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        mut pred: impl FnMut(&T) -> bool,
+        default: T,
+    ) -> bool
+    where
+        T: Clone + PartialEq,
+    {
+        let range = self.split_range(offset, len);
+        if range.is_empty() {
+            return false;
+        }
+        let mut changed = false;
+        for elem in &mut self.v[range.clone()] {
+            if !pred(&elem.data) {
+                elem.data = default.clone();
+                changed = true;
+            }
+        }
+        // Merge equal neighbours, including the elements just outside the range since they might
+        // now be equal to the reset ones.
+        let lo = range.start.saturating_sub(1);
+        let hi = (range.end + 1).min(self.v.len());
+        self.merge_window(lo..hi, |a, b| a == b);
+        changed
+    }
+
+    /// Overwrites everything in the given range with `data`. Entries in the map are only split
+    /// at the two ends of the range; everything in between is replaced by a single element.
+    /// This is much cheaper than assigning through `iter_mut` when the range covers many elements.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T)
+    where
+        T: Clone,
+    {
+        let offset = K::from_offset(offset);
+        let len = K::from_offset(len);
+        if len == K::ZERO {
+            // Nothing to overwrite.
+            return;
+        }
+        let end = range_end(offset, len);
+        assert!(end <= self.size(), "inserting beyond the bounds of this RangeMap");
+        self.splice_range(offset..end, iter::once(Elem { range: offset..end, data }));
+    }
+
+    /// Overwrites the range starting at `offset` with one value per index from `values`. Runs of
+    /// equal values are stored as a single element.
+    pub fn set_from_slice(&mut self, offset: K::Offset, values: &[T])
+    where
+        T: Clone + PartialEq,
+        K: TryFrom<usize>,
+    {
+        if values.is_empty() {
+            return;
+        }
+        let index = |i: usize| {
+            K::try_from(i).unwrap_or_else(|_| panic!("slice is too long for this RangeMap"))
+        };
+        let offset = K::from_offset(offset);
+        let end = range_end(offset, index(values.len()));
+        assert!(end <= self.size(), "inserting beyond the bounds of this RangeMap");
+        let mut elems: Vec<Elem<T, K>> = Vec::new();
+        let mut run_start = 0;
+        for i in 1..=values.len() {
+            if i == values.len() || values[i] != values[run_start] {
+                let range = offset + index(run_start)..offset + index(i);
+                elems.push(Elem { range, data: values[run_start].clone() });
+                run_start = i;
+            }
+        }
+        self.splice_range(offset..end, elems);
+    }
+
+    /// Like `insert_range`, but returns an error instead of panicking if the range is out of
+    /// bounds.
+    pub fn try_insert_range(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        data: T,
+    ) -> Result<(), OutOfBounds<K>>
+    where
+        T: Clone,
+    {
+        self.check_bounds(offset, len)?;
+        self.insert_range(offset, len, data);
+        Ok(())
+    }
+
+    /// Overwrites everything in the given range with `new`, but only if all of it currently maps
+    /// to `expected`. Otherwise, nothing is changed, and the first offset in the range that maps
+    /// to something else is returned.
+    pub fn compare_and_set(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+        expected: &T,
+        new: T,
+    ) -> Result<(), MismatchAt<K>>
+    where
+        T: Clone + PartialEq,
+    {
+        let start = K::from_offset(offset);
+        if let Some((range, _)) = self.iter(offset, len).find(|(_, data)| *data != expected) {
+            return Err(MismatchAt { offset: range.start.max(start) });
+        }
+        self.insert_range(offset, len, new);
+        Ok(())
+    }
+
+    // Replaces all elements in the given non-empty, in-bounds range by `elems`, splitting the
+    // elements at the edges of the range as needed. `elems` must cover exactly that range.
+    // Splitting clones the data of at most two elements; for `Copy` data, that clone is a plain
+    // copy, so there is no need for a separate write path for such data.
+    fn splice_range(&mut self, range: ops::Range<K>, elems: impl IntoIterator<Item = Elem<T, K>>)
+    where
+        T: Clone,
+    {
+        // `last_idx` is inclusive.
+        let first_idx = self.find_offset(range.start);
+        let last_idx = self.find_offset(range.end - K::ONE);
+        self.splice_elems(first_idx..=last_idx, range, elems);
+    }
+
+    /// Like `splice_range`, but takes the indices of the first and last element overlapping
+    /// `range` instead of searching for them.
+    fn splice_elems(
+        &mut self,
+        idxs: ops::RangeInclusive<usize>,
+        range: ops::Range<K>,
+        elems: impl IntoIterator<Item = Elem<T, K>>,
+    ) where
+        T: Clone,
+    {
+        let (first_idx, last_idx) = idxs.into_inner();
+        // Keep the parts of the boundary elements that stick out of the range. Rather than
+        // splitting them first, we put those parts into the same splice, so that the tail of the
+        // vector only gets shifted once.
+        let first = &self.v[first_idx];
+        let head = (first.range.start < range.start)
+            .then(|| Elem { range: first.range.start..range.start, data: first.data.clone() });
+        let last = &self.v[last_idx];
+        let tail = (last.range.end > range.end)
+            .then(|| Elem { range: range.end..last.range.end, data: last.data.clone() });
+        splice(&mut self.v, first_idx..last_idx + 1, head.into_iter().chain(elems).chain(tail));
+    }
+
+    /// Copies the data associated with the `len` bytes starting at `src` to the `len` bytes
+    /// starting at `dst`. The two ranges may overlap, like for `memmove`.
+    /// This copies entire elements, not individual bytes.
+    pub fn copy_within(&mut self, src: K::Offset, dst: K::Offset, len: K::Offset)
+    where
+        T: Clone,
+    {
+        let src = K::from_offset(src);
+        let dst = K::from_offset(dst);
+        let len = K::from_offset(len);
+        if len == K::ZERO {
+            // Nothing to copy.
+            return;
+        }
+        let dst_end = range_end(dst, len);
+        assert!(dst_end <= self.size(), "copying beyond the bounds of this RangeMap");
+        // Collect the source elements first, so that it does not matter if the ranges overlap.
+        let elems = self.clone_range(src, len, dst);
+        self.splice_range(dst..dst_end, elems);
+    }
+
+    /// Like `copy_within`, but with `memcpy` instead of `memmove` semantics: returns an error and
+    /// leaves the map unchanged if the two ranges overlap. Note the order of the arguments.
+    pub fn copy_nonoverlapping_from(
+        &mut self,
+        dst: K::Offset,
+        src: K::Offset,
+        len: K::Offset,
+    ) -> Result<(), OverlapError<K>>
+    where
+        T: Clone,
+    {
+        let src = K::from_offset(src);
+        let dst = K::from_offset(dst);
+        let len = K::from_offset(len);
+        let (src_end, dst_end) = (range_end(src, len), range_end(dst, len));
+        if len > K::ZERO && src < dst_end && dst < src_end {
+            return Err(OverlapError { src: src..src_end, dst: dst..dst_end });
+        }
+        self.copy_within(src.to_offset(), dst.to_offset(), len.to_offset());
+        Ok(())
+    }
+
+    /// Copies the data associated with the `len` bytes starting at `src_offset` in `src` to the
+    /// `len` bytes starting at `dst_offset` in `self`.
+    /// This copies entire elements, not individual bytes.
+    pub fn copy_from(
+        &mut self,
+        dst_offset: K::Offset,
+        src: &RangeMap<T, K>,
+        src_offset: K::Offset,
+        len: K::Offset,
+    ) where
+        T: Clone,
+    {
+        let dst = K::from_offset(dst_offset);
+        let len = K::from_offset(len);
+        if len == K::ZERO {
+            // Nothing to copy.
+            return;
+        }
+        let dst_end = range_end(dst, len);
+        assert!(dst_end <= self.size(), "copying beyond the bounds of this RangeMap");
+        let elems = src.clone_range(K::from_offset(src_offset), len, dst);
+        self.splice_range(dst..dst_end, elems);
+    }
+
+    // Clones the elements covering the `len` bytes starting at `offset`, clipped to that range
+    // and moved such that they start at `new_offset` instead.
+    fn clone_range(&self, offset: K, len: K, new_offset: K) -> Vec<Elem<T, K>>
+    where
+        T: Clone,
+    {
+        self.iter_clipped(offset.to_offset(), len.to_offset())
+            .map(|(range, data)| {
+                let range = range.start - offset + new_offset..range.end - offset + new_offset;
+                Elem { range, data: data.clone() }
+            })
+            .collect()
+    }
+
+    /// Restores everything in the given range to `init`, which should be the value this map was
+    /// created with. Unlike `insert_range`, this also merges the reset range with its neighbours
+    /// if they are equal, so resetting part of a map does not leave behind extra fragments.
+    pub fn reset_range(&mut self, offset: K::Offset, len: K::Offset, init: T)
+    where
+        T: Clone + PartialEq,
+    {
+        if K::from_offset(len) == K::ZERO {
+            // Nothing to reset.
+            return;
+        }
+        self.insert_range(offset, len, init);
+        let idx = self.find_offset(K::from_offset(offset));
+        self.merge_with_neighbours(idx);
+    }
+
+    /// Grows the map to cover `new_size` bytes, associating the newly covered range with `fill`.
+    /// The data of the already covered range is preserved.
+    pub fn grow(&mut self, new_size: K::Offset, fill: T) {
+        let old_size = self.size();
+        let new_size = K::from_offset(new_size);
+        assert!(new_size >= old_size, "cannot grow a RangeMap to a smaller size");
+        if new_size > old_size {
+            self.v.push(Elem { range: old_size..new_size, data: fill });
+        }
+    }
+
+    /// Shrinks the map to cover only the first `new_size` bytes, dropping the data of the tail.
+    pub fn truncate(&mut self, new_size: K::Offset) {
+        let old_size = self.size();
+        let new_size = K::from_offset(new_size);
+        assert!(new_size <= old_size, "cannot truncate a RangeMap to a larger size");
+        if new_size == K::ZERO {
+            self.v.clear();
+        } else if new_size < old_size {
+            // Keep everything up to and including the element containing the new last byte.
+            let last_idx = self.find_offset(new_size - K::ONE);
+            self.v.truncate(last_idx + 1);
+            self.v[last_idx].range.end = new_size;
+        }
+    }
+
+    /// Splits the map in two at the given offset, like `Vec::split_off`: `self` keeps the range
+    /// up to `at`, and the rest is returned as a separate map. Offsets in the returned map are
+    /// relative to `at`, i.e., it starts at 0 like any other map.
+    #[must_use]
+    pub fn split_off(&mut self, at: K::Offset) -> RangeMap<T, K>
+    where
+        T: Clone,
+    {
+        let at = K::from_offset(at);
+        assert!(at <= self.size(), "splitting a RangeMap beyond its size");
+        if at == self.size() {
+            return RangeMap::from_elems(SmallVec::new(), self.merge_budget);
+        }
+        let mut tail: Elems<T, K> = self.v.drain(self.find_offset(at)..).collect();
+        let first = &mut tail[0];
+        if first.range.start < at {
+            // The element containing `at` needs to be split between the two maps.
+            self.v.push(Elem { range: first.range.start..at, data: first.data.clone() });
+            first.range.start = at;
+        }
+        for elem in &mut tail {
+            elem.range = elem.range.start - at..elem.range.end - at;
+        }
+        RangeMap::from_elems(tail, self.merge_budget)
+    }
+
+    /// Appends `other` to this map, like `Vec::append`: offset 0 of `other` ends up at the current
+    /// size of `self`. The elements at the seam are merged if their data is equal.
+    pub fn append(&mut self, other: RangeMap<T, K>)
+    where
+        T: PartialEq,
+    {
+        let offset = self.size();
+        let mut elems = other
+            .v
+            .into_iter()
+            .map(|elem| {
+                Elem {
+                    range: range_end(offset, elem.range.start)..range_end(offset, elem.range.end),
+                    data: elem.data,
+                }
+            })
+            .peekable();
+        if let Some(last) = self.v.last_mut() {
+            if let Some(first) = elems.next_if(|first| first.data == last.data) {
+                last.range.end = first.range.end;
+            }
+        }
+        self.v.extend(elems);
+    }
+
+    /// Converts this map into a map with the same elements, where the data of each element is
+    /// computed by `f` from its range and its old data.
+    pub fn map<U>(self, mut f: impl FnMut(ops::Range<K>, T) -> U) -> RangeMap<U, K> {
+        let v = self
+            .v
+            .into_iter()
+            .map(|elem| Elem { range: elem.range.clone(), data: f(elem.range, elem.data) })
+            .collect();
+        RangeMap::from_elems(v, self.merge_budget)
+    }
+
+    /// Like `map`, but borrows this map instead of consuming it.
+    pub fn map_ref<U>(&self, mut f: impl FnMut(ops::Range<K>, &T) -> U) -> RangeMap<U, K> {
+        let v = self
+            .v
+            .iter()
+            .map(|elem| Elem { range: elem.range.clone(), data: f(elem.range.clone(), &elem.data) })
+            .collect();
+        RangeMap::from_elems(v, self.merge_budget)
+    }
+
+    /// Combines this map with `other`, which must have the same size, by applying `f` to the
+    /// data of both maps. The result is split wherever either of the two maps is split.
+    pub fn zip_with<U, V>(
+        &self,
+        other: &RangeMap<U, K>,
+        mut f: impl FnMut(&T, &U) -> V,
+    ) -> RangeMap<V, K> {
+        assert!(self.size() == other.size(), "zipping RangeMaps of different sizes");
+        let v = zip_elems(&self.v, &other.v, K::ZERO, self.size())
+            .map(|(range, a, b)| Elem { range, data: f(a, b) })
+            .collect();
+        RangeMap::from_elems(v, self.merge_budget)
+    }
+
+    /// Combines this map with `other`, which must have the same size, by applying `f` to the
+    /// data of both maps, like `zip_with`. Unlike `zip_with`, neighbouring elements with equal
+    /// results are merged, so the result does not depend on how the two maps are split.
+    pub fn merge_with(&self, other: &RangeMap<T, K>, mut f: impl FnMut(&T, &T) -> T) -> Self
+    where
+        T: PartialEq,
+    {
+        assert!(self.size() == other.size(), "merging RangeMaps of different sizes");
+        let mut v: Elems<T, K> = SmallVec::new();
+        for (range, a, b) in zip_elems(&self.v, &other.v, K::ZERO, self.size()) {
+            let data = f(a, b);
+            match v.last_mut() {
+                Some(last) if last.data == data => last.range.end = range.end,
+                _ => v.push(Elem { range, data }),
+            }
+        }
+        RangeMap::from_elems(v, self.merge_budget)
+    }
+
+    /// Provides read-only iteration over the given range of both this map and `other`, yielding
+    /// the data of both maps for every part of the range where neither map is split.
+    /// The provided ranges are clipped to the given range.
+    pub fn iter_both<'a, U>(
+        &'a self,
+        other: &'a RangeMap<U, K>,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &'a T, &'a U)> {
+        let start = K::from_offset(offset);
+        let end = range_end(start, K::from_offset(len));
+        let (a, b): (&[Elem<T, K>], &[Elem<U, K>]) = if start == end {
+            // Nothing to iterate over.
+            (&[], &[])
+        } else {
+            assert!(
+                end <= self.size() && end <= other.size(),
+                "iterating beyond the bounds of this RangeMap"
+            );
+            (&self.v[self.find_offset(start)..], &other.v[other.find_offset(start)..])
+        };
+        zip_elems(a, b, start, end)
+    }
+
+    /// Compares this map with `other`, which must have the same size, and yields all ranges in
+    /// which the two maps disagree, together with the data of both maps. Adjacent ranges with
+    /// the same data are yielded together, so the result does not depend on how the maps are
+    /// split internally.
+    pub fn diff<'a>(
+        &'a self,
+        other: &'a RangeMap<T, K>,
+    ) -> impl Iterator<Item = (ops::Range<K>, &'a T, &'a T)>
+    where
+        T: PartialEq,
+    {
+        assert!(self.size() == other.size(), "diffing RangeMaps of different sizes");
+        let mut diffs = zip_elems(&self.v, &other.v, K::ZERO, self.size())
+            .filter(|(_, a, b)| a != b)
+            .peekable();
+        iter::from_fn(move || {
+            let (mut range, a, b) = diffs.next()?;
+            while let Some((next, _, _)) = diffs.next_if(|(next, next_a, next_b)| {
+                next.start == range.end && *next_a == a && *next_b == b
+            }) {
+                range.end = next.end;
+            }
+            Some((range, a, b))
+        })
+    }
+
+    /// Returns whether this map and `other` have the same size and associate every offset with
+    /// equal data. Unlike comparing the elements, this does not depend on how the maps are split.
+    pub fn semantic_eq(&self, other: &RangeMap<T, K>) -> bool
+    where
+        T: PartialEq,
+    {
+        self.size() == other.size() && self.diff(other).next().is_none()
+    }
+
+    /// Hashes the content of this map, such that maps that are `semantic_eq` hash the same no
+    /// matter how they are split.
+    pub fn hash_semantic<H: Hasher>(&self, state: &mut H)
+    where
+        T: Hash + PartialEq,
+        K: Hash,
+    {
+        for (range, data) in self.iter_maximal() {
+            range.hash(state);
+            data.hash(state);
+        }
+    }
+
+    /// Applies `patch`, which must have the same size as this map, on top of this map: all ranges
+    /// that `patch` associates with `Some(data)` are overwritten with `data`, and everything else
+    /// is left unchanged. This rebuilds the map in a single pass, merging neighbouring elements
+    /// that end up equal.
+    pub fn apply_overlay(&mut self, patch: &RangeMap<Option<T>, K>)
+    where
+        T: Clone + PartialEq,
+    {
+        assert!(self.size() == patch.size(), "applying an overlay of a different size");
+        let mut v: Elems<T, K> = SmallVec::with_capacity(self.v.len());
+        for (range, base, patch) in zip_elems(&self.v, &patch.v, K::ZERO, self.size()) {
+            let data = patch.as_ref().unwrap_or(base);
+            match v.last_mut() {
+                Some(last) if last.data == *data => last.range.end = range.end,
+                _ => v.push(Elem { range, data: data.clone() }),
+            }
+        }
+        self.v = v;
+    }
+
+    /// Overwrites every range yielded by `runs` with the data yielded along with it, and leaves
+    /// everything else unchanged. The runs must be sorted and must not overlap; in exchange, they
+    /// are applied in a single pass over the map rather than one lookup per run, e.g. to apply
+    /// the result of `diff`. Neighbouring elements that end up equal are merged.
+    pub fn merge_sorted(&mut self, runs: impl IntoIterator<Item = (ops::Range<K>, T)>)
+    where
+        T: Clone + PartialEq,
+    {
+        fn push<T: PartialEq, K: RangeKey>(v: &mut Elems<T, K>, elem: Elem<T, K>) {
+            match v.last_mut() {
+                Some(last) if last.data == elem.data => last.range.end = elem.range.end,
+                _ => v.push(elem),
+            }
+        }
+
+        let size = self.size();
+        let mut old = mem::take(&mut self.v).into_iter().peekable();
+        let mut v: Elems<T, K> = SmallVec::with_capacity(old.len());
+        // Moves the parts of the old elements in `pos..end` to `v`, where everything before `pos`
+        // has already been dealt with.
+        let mut keep_old = |v: &mut Elems<T, K>, pos: K, end: K| {
+            while let Some(elem) = old.peek_mut() {
+                // The element may have started before `pos`, e.g. if it was partially covered by
+                // the previous run, so compare the part that is left.
+                let start = elem.range.start.max(pos);
+                if start >= end {
+                    break;
+                }
+                if elem.range.end > end {
+                    // The rest of the element is still needed.
+                    push(v, Elem { range: start..end, data: elem.data.clone() });
+                    elem.range.start = end;
+                    break;
+                }
+                let elem = old.next().unwrap();
+                if elem.range.end > pos {
+                    push(v, Elem { range: start..elem.range.end, data: elem.data });
+                }
+            }
+        };
+        let mut pos = K::ZERO;
+        for (range, data) in runs {
+            assert!(
+                range.start >= pos && range.start <= range.end,
+                "runs passed to merge_sorted must be sorted and disjoint"
+            );
+            assert!(range.end <= size, "merging a run beyond the bounds of this RangeMap");
+            if range.is_empty() {
+                continue;
+            }
+            keep_old(&mut v, pos, range.start);
+            push(&mut v, Elem { range: range.clone(), data });
+            pos = range.end;
+        }
+        keep_old(&mut v, pos, size);
+        self.v = v;
+    }
+
+    // Merges the element at `index` with its left and right neighbour, if they carry equal data.
+    fn merge_with_neighbours(&mut self, index: usize)
+    where
+        T: PartialEq,
+    {
+        if index + 1 < self.v.len() && self.v[index + 1].data == self.v[index].data {
+            let next = self.v.remove(index + 1);
+            self.v[index].range.end = next.range.end;
+        }
+        if index > 0 && self.v[index - 1].data == self.v[index].data {
+            let elem = self.v.remove(index);
+            self.v[index - 1].range.end = elem.range.end;
+        }
+    }
+
+    /// Remove all adjacent duplicates.
+    ///
+    /// Unlike the opportunistic merging done by `iter_mut`, this is a full pass over the entire
+    /// map, so it can be used to defragment long-lived maps at controlled points.
+    pub fn merge_adjacent_thorough(&mut self)
+    where
+        T: PartialEq,
+    {
+        self.v.dedup_by(|elem, prev| {
+            if prev.data == elem.data {
+                assert_eq!(prev.range.end, elem.range.start);
+                prev.range.end = elem.range.end;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Returns the number of elements this map can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.v.capacity()
+    }
+
+    /// Frees as much unused capacity as possible, e.g. after `merge_adjacent_thorough`.
+    pub fn shrink_to_fit(&mut self) {
+        self.v.shrink_to_fit();
+    }
+}
+
+/// Maps that store their data behind an `Rc` make splitting cheap, since only the `Rc` needs to
+/// be cloned. The data itself is only cloned once it is actually mutated.
+impl<T: Clone + PartialEq, K: RangeKey> RangeMap<Rc<T>, K> {
+    /// Like `iter_mut`, but provides mutable access to the shared data. The data of an element is
+    /// cloned only if it is still shared with other elements (or other users of the `Rc`).
+    pub fn iter_mut_shared(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> IterMutShared<'_, T, K> {
+        IterMutShared::new(self.iter_mut(offset, len))
+    }
+}
+
+/// Iterates over the common refinement of `a` and `b` in the range `start..end`. Both slices must
+/// start with the element containing `start`, and cover the entire range.
+fn zip_elems<'a, T, U, K: RangeKey>(
+    a: &'a [Elem<T, K>],
+    b: &'a [Elem<U, K>],
+    start: K,
+    end: K,
+) -> impl Iterator<Item = (ops::Range<K>, &'a T, &'a U)> {
+    let mut a = a.iter();
+    let mut b = b.iter();
+    let mut cur_a = a.next();
+    let mut cur_b = b.next();
+    let mut pos = start;
+    iter::from_fn(move || {
+        if pos >= end {
+            return None;
+        }
+        let (elem_a, elem_b) = (cur_a.unwrap(), cur_b.unwrap());
+        let next = elem_a.range.end.min(elem_b.range.end).min(end);
+        let range = pos..next;
+        // Move on in whichever map(s) we reached the end of the current element.
+        if elem_a.range.end == next {
+            cur_a = a.next();
+        }
+        if elem_b.range.end == next {
+            cur_b = b.next();
+        }
+        pos = next;
+        Some((range, &elem_a.data, &elem_b.data))
+    })
+}
+
+impl<T, K: RangeKey> FromIterator<(ops::Range<K>, T)> for RangeMap<T, K> {
+    fn from_iter<I: IntoIterator<Item = (ops::Range<K>, T)>>(iter: I) -> Self {
+        RangeMap::from_sorted_ranges(iter)
+    }
+}
+
+impl<T: fmt::Debug, K: fmt::Debug> fmt::Debug for Elem<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} => {:?}", self.range, self.data)
+    }
+}
+
+/// Prints the elements as `[0..8 => A, 8..16 => B]`, showing how exactly the map is split.
+impl<T: fmt::Debug, K: fmt::Debug> fmt::Debug for RangeMap<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.v).finish()
+    }
+}
+
+/// Prints the maximal ranges with equal data as `0..8 => A, 8..16 => B`, independently of how the
+/// map is split.
+impl<T: fmt::Display + PartialEq, K: RangeKey> fmt::Display for RangeMap<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, (range, data)) in self.iter_maximal().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{range:?} => {data}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::hash_map::DefaultHasher;
+
+    use super::*;
+
+    /// Query the map at every offset in the range and collect the results.
+    pub(super) fn to_vec<T: Copy>(map: &RangeMap<T>, offset: u64, len: u64) -> Vec<T> {
+        read_range(offset, len, |i| *map.get(i))
+    }
+
+    /// Like `to_vec`, but for any kind of map: `get` queries the map at the given offset.
+    pub(super) fn read_range<T>(offset: u64, len: u64, get: impl Fn(u64) -> T) -> Vec<T> {
+        (offset..offset + len).map(get).collect()
+    }
+
+    /// A map of size 20 that is -1 everywhere, except for 42 in `5..10`.
+    pub(super) fn sample_map() -> RangeMap<i32> {
+        let mut map = RangeMap::new(20, -1);
+        map.insert_range(5, 5, 42);
+        map
+    }
+
+    #[test]
+    fn basic_insert() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        // Insert.
+        for (_, x) in &mut map.iter_mut(10, 1) {
+            *x = 42;
+        }
+        // Check.
+        assert_eq!(to_vec(&map, 10, 1), vec![42]);
+        assert_eq!(map.v.len(), 3);
+
+        // Insert with size 0.
+        for (_, x) in &mut map.iter_mut(10, 0) {
+            *x = 19;
+        }
+        for (_, x) in &mut map.iter_mut(11, 0) {
+            *x = 19;
+        }
+        assert_eq!(to_vec(&map, 10, 2), vec![42, -1]);
+        assert_eq!(map.v.len(), 3);
+    }
+
+    #[test]
+    fn stats() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        assert_eq!(
+            map.stats(),
+            RangeMapStats { runs: 1, average_run_len: 20, largest_run: 20, heap_bytes: 0 }
+        );
+        map.insert_range(2, 2, 42);
+        let stats = map.stats();
+        assert_eq!((stats.runs, stats.average_run_len, stats.largest_run), (3, 6, 16));
+        assert!(stats.heap_bytes >= 3 * mem::size_of::<Elem<i32, u64>>());
+        assert_eq!(RangeMap::<i32>::new(0, -1).stats().average_run_len, 0);
+    }
+
+    #[test]
+    fn heap_size_of() {
+        let mut map = RangeMap::<Vec<u8>>::new(20, Vec::new());
+        assert_eq!(map.heap_size_of(Vec::capacity), 0);
+        map.insert_range(5, 5, Vec::with_capacity(8));
+        let elems = map.stats().heap_bytes;
+        assert!(elems > 0);
+        assert_eq!(map.heap_size_of(Vec::capacity), elems + 8);
+        assert_eq!(map.heap_size_of(|_| 1), elems + 3);
+    }
+
+    #[test]
+    fn inline_storage() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        assert!(!map.v.spilled());
+        map.insert_range(5, 5, 42);
+        assert!(map.v.spilled());
+        map.insert_range(0, 20, 0);
+        map.shrink_to_fit();
+        assert!(!map.v.spilled());
+    }
+
+    #[test]
+    fn capacity() {
+        let mut map = RangeMap::<i32>::with_capacity(20, -1, 16);
+        assert!(map.capacity() >= 16);
+        for i in 0..8 {
+            map.insert_range(i * 2, 1, 42);
+        }
+        map.insert_range(0, 20, 0);
+        map.shrink_to_fit();
+        assert_eq!(map.num_runs(), 1);
+        assert!(map.capacity() < 16);
+    }
+
+    #[test]
+    fn set_from_slice() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        map.set_from_slice(3, &[1, 1, 1, 2, 2, 1, 3]);
+        map.set_from_slice(15, &[]);
+        map.validate();
+        assert_eq!(to_vec(&map, 0, 12), vec![-1, -1, -1, 1, 1, 1, 2, 2, 1, 3, -1, -1]);
+        assert_eq!(map.num_runs(), 6);
+        map.set_from_slice(18, &[4, 4]);
+        assert_eq!(to_vec(&map, 17, 3), vec![-1, 4, 4]);
+    }
+
+    #[test]
+    fn gaps() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        for (_, x) in &mut map.iter_mut(11, 1) {
+            *x = 42;
+        }
+        for (_, x) in &mut map.iter_mut(15, 1) {
+            *x = 43;
+        }
+        assert_eq!(map.v.len(), 5);
+        assert_eq!(to_vec(&map, 10, 10), vec![-1, 42, -1, -1, -1, 43, -1, -1, -1, -1]);
+
+        for (_, x) in &mut map.iter_mut(10, 10) {
+            if *x < 42 {
+                *x = 23;
+            }
+        }
+        assert_eq!(map.v.len(), 6);
+        assert_eq!(to_vec(&map, 10, 10), vec![23, 42, 23, 23, 23, 43, 23, 23, 23, 23]);
+        assert_eq!(to_vec(&map, 13, 5), vec![23, 23, 43, 23, 23]);
+
+        for (_, x) in &mut map.iter_mut(15, 5) {
+            *x = 19;
+        }
+        // The two blocks with 19 got merged once the iteration was done.
+        assert_eq!(map.v.len(), 5);
+        assert_eq!(to_vec(&map, 10, 10), vec![23, 42, 23, 23, 23, 19, 19, 19, 19, 19]);
+        assert_eq!(map.iter(15, 2).map(|(_, &t)| t).collect::<Vec<_>>(), vec![19]);
+
+        // A NOP `iter_mut` does not change anything.
+        for _ in &mut map.iter_mut(15, 5) {}
+        assert_eq!(map.v.len(), 5);
+        assert_eq!(to_vec(&map, 10, 10), vec![23, 42, 23, 23, 23, 19, 19, 19, 19, 19]);
+    }
+
+    #[test]
+    fn iter_mut_splits_and_merges() {
+        let mut map = RangeMap::<i32>::new(20, 0);
+        for i in 0..10 {
+            map.insert_range(2 * i, 2, i32::from(i % 2 == 0));
+        }
+        assert_eq!(map.v.len(), 10);
+        // Splits at both ends, with mergeable blocks in between.
+        for (_, x) in &mut map.iter_mut(3, 14) {
+            *x = 7;
+        }
+        assert_eq!(
+            to_vec(&map, 0, 20),
+            vec![1, 1, 0, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 1, 0, 0]
+        );
+        // The iteration covers exactly the given range.
+        let ranges: Vec<_> = (&mut map.iter_mut(5, 13)).into_iter().map(|(r, _)| r).collect();
+        assert_eq!(ranges.first().unwrap().start, 5);
+        assert_eq!(ranges.last().unwrap().end, 18);
+        assert!(ranges.windows(2).all(|w| w[0].end == w[1].start));
+        // Splitting a single element at both ends.
+        for (range, x) in &mut map.iter_mut(8, 2) {
+            assert_eq!(range, 8..10);
+            *x = 9;
+        }
+        assert_eq!(to_vec(&map, 6, 6), vec![7, 7, 9, 9, 7, 7]);
+        map.validate();
+    }
+
+    #[test]
+    fn first_and_last_run() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        map.insert_range(15, 5, 42);
+        assert_eq!(map.first_run(), Some((0..15, &-1)));
+        assert_eq!(map.last_run(), Some((15..20, &42)));
+        *map.last_run_mut().unwrap().1 += 1;
+        *map.first_run_mut().unwrap().1 -= 1;
+        assert_eq!(to_vec(&map, 14, 2), vec![-2, 43]);
+        let mut empty = RangeMap::<i32>::new(0, -1);
+        assert_eq!(empty.first_run(), None);
+        assert_eq!(empty.last_run_mut(), None);
+    }
+
+    #[test]
+    fn iter_mut_masked() {
+        let mut map = sample_map();
+        let mut mask = RangeMap::<bool>::new(20, false);
+        mask.insert_range(2, 5, true);
+        // Split without changing anything, so that the masked ranges need to be coalesced.
+        mask.insert_range(4, 1, true);
+        mask.insert_range(12, 8, true);
+        let mut ranges = Vec::new();
+        for (range, x) in &mut map.iter_mut_masked(3, 12, &mask) {
+            ranges.push(range);
+            *x = 0;
+        }
+        assert_eq!(ranges, vec![3..5, 5..7, 12..15]);
+        assert_eq!(
+            to_vec(&map, 0, 20),
+            vec![-1, -1, -1, 0, 0, 0, 0, 42, 42, 42, -1, -1, 0, 0, 0, -1, -1, -1, -1, -1]
+        );
+    }
+
+    #[test]
+    fn iter_mut_where() {
+        let mut map = sample_map();
+        let mut ranges = Vec::new();
+        for (range, x) in &mut map.iter_mut_where(3, 12, |&x| x < 0) {
+            ranges.push(range);
+            *x = 0;
+        }
+        assert_eq!(ranges, vec![3..5, 10..15]);
+        assert_eq!(map.num_runs(), 5);
+        // Nothing satisfies the predicate, so nothing is split.
+        let mut iter = map.iter_mut_where(6, 2, |&x| x < 0);
+        assert_eq!(iter.into_iter().count(), 0);
+        drop(iter);
+        assert_eq!(map.num_runs(), 5);
+    }
+
+    #[test]
+    fn merge_sorted() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        map.insert_range(5, 10, 42);
+        let mut target = map.clone();
+        target.insert_range(2, 2, 0);
+        target.insert_range(7, 2, 43);
+        target.insert_range(14, 4, 43);
+        let delta: Vec<_> = map.diff(&target).map(|(range, _, &data)| (range, data)).collect();
+        map.merge_sorted(delta);
+        assert!(map.semantic_eq(&target));
+        map.validate();
+        assert_eq!(
+            map.iter_all().collect::<Vec<_>>(),
+            vec![
+                (0..2, &-1),
+                (2..4, &0),
+                (4..5, &-1),
+                (5..7, &42),
+                (7..9, &43),
+                (9..14, &42),
+                (14..18, &43),
+                (18..20, &-1)
+            ]
+        );
+        map.merge_sorted([(0..0, 1), (0..20, 1)]);
+        assert_eq!(map.num_runs(), 1);
+    }
+
+    #[test]
+    fn merge_sorted_adjacent() {
+        let mut map = RangeMap::<i32>::new(20, 0);
+        map.merge_sorted([(0..5, 1), (5..10, 2)]);
+        map.validate();
+        assert_eq!(map.iter_all().collect::<Vec<_>>(), vec![(0..5, &1), (5..10, &2), (10..20, &0)]);
+        // Adjacent runs in the middle of an element, and one right after them.
+        map.merge_sorted([(11..13, 3), (13..15, 4), (15..16, 3)]);
+        map.validate();
+        assert_eq!(to_vec(&map, 9, 9), vec![2, 0, 3, 3, 4, 4, 3, 0, 0]);
+    }
+
+    #[test]
+    fn compare_and_set() {
+        let mut map = sample_map();
+        assert_eq!(map.compare_and_set(2, 6, &-1, 0), Err(MismatchAt { offset: 5 }));
+        assert_eq!(map.compare_and_set(7, 6, &42, 0), Err(MismatchAt { offset: 10 }));
+        assert_eq!(to_vec(&map, 0, 20)[..11], [-1, -1, -1, -1, -1, 42, 42, 42, 42, 42, -1]);
+        assert_eq!(map.compare_and_set(6, 3, &42, 43), Ok(()));
+        assert_eq!(to_vec(&map, 4, 7), vec![-1, 42, 43, 43, 43, 42, -1]);
+    }
+
+    #[test]
+    fn range_bounds() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        for (_, x) in &mut map.iter_mut_range(5..10) {
+            *x = 42;
+        }
+        for (_, x) in &mut map.iter_mut_range(15..) {
+            *x = 43;
+        }
+        assert_eq!(map.iter_range(..=5).collect::<Vec<_>>(), vec![(0..5, &-1), (5..10, &42)]);
+        assert_eq!(map.iter_range(..).count(), 4);
+        assert_eq!(map.iter_range(12..12).count(), 0);
+        assert_eq!(
+            map.iter_range((ops::Bound::Excluded(14), ops::Bound::Unbounded)).collect::<Vec<_>>(),
+            vec![(15..20, &43)]
+        );
+    }
+
+    #[test]
+    fn iter_mut_multi() {
+        let mut map = sample_map();
+        let ranges = [(2, 6), (8, 0), (8, 4), (15, 5)];
+        for (i, group) in map.iter_mut_multi(&ranges).by_range().enumerate() {
+            for (_, x) in group {
+                *x = i32::try_from(i).unwrap();
+            }
+        }
+        map.validate();
+        assert_eq!(
+            to_vec(&map, 0, 20),
+            vec![-1, -1, 0, 0, 0, 0, 0, 0, 2, 2, 2, 2, -1, -1, -1, 3, 3, 3, 3, 3]
+        );
+    }
+
+    #[test]
+    fn merge_on_drop() {
+        let mut map = RangeMap::<i32>::new(20, 0);
+        map.insert_range(5, 5, 42);
+        map.insert_range(10, 5, 43);
+        assert_eq!(map.num_runs(), 4);
+        // Merging happens once the guard is dropped, so it takes the new values into account,
+        // including the neighbours of the mutated range.
+        let mut iter = map.iter_mut(5, 10);
+        for (_, x) in &mut iter {
+            *x = 0;
+        }
+        drop(iter);
+        assert_eq!(map.num_runs(), 1);
+        for (_, x) in &mut map.iter_mut(2, 16) {
+            *x = 1;
+        }
+        assert_eq!(map.num_runs(), 3);
+        // Not visiting all elements merges as well.
+        let mut iter = map.iter_mut(0, 20);
+        *iter.into_iter().next().unwrap().1 = 1;
+        drop(iter);
+        assert_eq!(map.num_runs(), 2);
+        map.validate();
+    }
+
+    #[test]
+    fn merge_budget() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        for i in 0..10u64 {
+            map.insert_range(i * 2, 1, i32::try_from(i).unwrap());
+        }
+        assert_eq!(map.num_runs(), 20);
+        let reset = |map: &mut RangeMap<i32>| {
+            for (_, x) in &mut map.iter_mut(0, 20) {
+                *x = 0;
+            }
+        };
+        // Merging disabled: nothing gets merged.
+        map.set_merge_budget(0);
+        reset(&mut map);
+        assert_eq!(map.num_runs(), 20);
+        // Unbounded budget: everything gets merged.
+        map.set_merge_budget(usize::MAX);
+        reset(&mut map);
+        assert_eq!(map.num_runs(), 1);
+    }
+
+    #[test]
+    fn retain() {
+        let mut map = RangeMap::<i32>::new(20, 0);
+        for i in 1..10u64 {
+            map.insert_range(i * 2, 2, i32::try_from(i).unwrap());
+        }
+        // Reset all odd values in 3..15, which splits the boundary elements.
+        assert!(map.retain(3, 12, |x| x % 2 == 0, 0));
+        map.validate();
+        assert_eq!(
+            to_vec(&map, 0, 20),
+            vec![0, 0, 1, 0, 2, 2, 0, 0, 4, 4, 0, 0, 6, 6, 0, 7, 8, 8, 9, 9]
+        );
+        assert_eq!(map.num_runs(), 12);
+        assert!(!map.retain(3, 12, |x| x % 2 == 0, 0));
+        // Resetting everything merges it all into one element.
+        assert!(map.retain(0, 20, |_| false, 0));
+        assert_eq!(map.num_runs(), 1);
+    }
+
+    #[test]
+    fn for_each_mut() {
+        let mut map = sample_map();
+        // Nothing changes, so nothing gets split.
+        map.for_each_mut(3, 10, |_, _| false);
+        assert_eq!(map.v.len(), 3);
+        let mut ranges = Vec::new();
+        map.for_each_mut(3, 10, |range, x| {
+            ranges.push(range);
+            if *x < 0 {
+                return false;
+            }
+            *x += 1;
+            true
+        });
+        assert_eq!(ranges, vec![3..5, 5..10, 10..13]);
+        assert_eq!(map.v.len(), 3);
+        assert_eq!(to_vec(&map, 4, 7), vec![-1, 43, 43, 43, 43, 43, -1]);
+        // Changing a partially covered element splits it.
+        map.for_each_mut(8, 4, |_, x| {
+            *x = 0;
+            true
+        });
+        assert_eq!(map.v.len(), 5);
+        assert_eq!(to_vec(&map, 7, 6), vec![43, 0, 0, 0, 0, -1]);
+    }
+
+    #[test]
+    fn update_range() {
+        let mut map = sample_map();
+        // Writing the same value again is not a change.
+        assert!(!map.update_range(3, 10, |x| { *x = x.abs() * x.signum() }));
+        assert!(!map.update_range(3, 0, |x| *x = 0));
+        assert_eq!(map.v.len(), 3);
+        assert!(map.update_range(3, 10, |x| {
+            if *x > 0 {
+                *x += 1;
+            }
+        }));
+        assert_eq!(map.v.len(), 3);
+        assert_eq!(to_vec(&map, 4, 7), vec![-1, 43, 43, 43, 43, 43, -1]);
+    }
+
+    #[test]
+    fn insert_range() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        for (_, x) in &mut map.iter_mut(11, 1) {
+            *x = 42;
+        }
+        for (_, x) in &mut map.iter_mut(15, 1) {
+            *x = 43;
+        }
+        assert_eq!(map.v.len(), 5);
+
+        // Overwrite a range covering several elements, with partial overlap at both ends.
+        map.insert_range(10, 7, 19);
+        assert_eq!(map.v.len(), 3);
+        assert_eq!(to_vec(&map, 8, 12), vec![-1, -1, 19, 19, 19, 19, 19, 19, 19, -1, -1, -1]);
+
+        // Overwrite the middle of a single element.
+        map.insert_range(12, 2, 7);
+        assert_eq!(map.v.len(), 5);
+        assert_eq!(to_vec(&map, 10, 8), vec![19, 19, 7, 7, 19, 19, 19, -1]);
+
+        // Inserting with size 0 does nothing.
+        map.insert_range(12, 0, 0);
+        assert_eq!(map.v.len(), 5);
+
+        // Overwrite everything.
+        map.insert_range(0, 20, 0);
+        assert_eq!(map.v.len(), 1);
+        assert_eq!(to_vec(&map, 0, 20), vec![0; 20]);
+    }
+
+    #[test]
+    fn reset_range() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        map.insert_range(5, 10, 42);
+        map.insert_range(8, 2, 43);
+        assert_eq!(map.v.len(), 5);
+
+        // Resetting the tail of the range merges it with the untouched part after it.
+        map.reset_range(12, 3, -1);
+        assert_eq!(map.v.len(), 5);
+        assert_eq!(to_vec(&map, 10, 10), vec![42, 42, -1, -1, -1, -1, -1, -1, -1, -1]);
+
+        // Resetting everything that is not `-1` gives back a single element.
+        map.reset_range(5, 7, -1);
+        assert_eq!(map.v.len(), 1);
+        assert_eq!(to_vec(&map, 0, 20), vec![-1; 20]);
+    }
+
+    #[test]
+    fn iter_ranges() {
+        let map = sample_map();
+        // `iter` reports the full range of every element it yields.
+        assert_eq!(map.iter(7, 5).collect::<Vec<_>>(), vec![(5..10, &42), (10..20, &-1)]);
+        assert_eq!(
+            map.iter_all().map(|(range, _)| range).collect::<Vec<_>>(),
+            vec![0..5, 5..10, 10..20]
+        );
+    }
+
+    #[test]
+    fn iter_rev() {
+        let map = sample_map();
+        assert_eq!(
+            map.iter_rev(3, 8).collect::<Vec<_>>(),
+            vec![(10..20, &-1), (5..10, &42), (0..5, &-1)]
+        );
+        // Find the last element with 42 before offset 15.
+        assert_eq!(map.iter_rev(0, 15).find(|(_, &x)| x == 42), Some((5..10, &42)));
+        assert_eq!(map.iter_rev(3, 0).count(), 0);
+    }
+
+    #[test]
+    fn iter_clipped() {
+        let map = sample_map();
+        assert_eq!(map.iter_clipped(7, 5).collect::<Vec<_>>(), vec![(7..10, &42), (10..12, &-1)]);
+        assert_eq!(map.iter_clipped(6, 2).collect::<Vec<_>>(), vec![(6..8, &42)]);
+        assert_eq!(map.iter_clipped(6, 0).count(), 0);
+    }
+
+    #[test]
+    fn get() {
+        let map = sample_map();
+        assert_eq!(map.get(4), &-1);
+        assert_eq!(map.get(5), &42);
+        assert_eq!(map.try_get(19), Some(&-1));
+        assert_eq!(map.try_get(20), None);
+        assert_eq!(RangeMap::<i32>::new(0, -1).try_get(0), None);
+    }
+
+    #[test]
+    fn range_containing() {
+        let mut map = sample_map();
+        assert_eq!(map.range_containing(7), (5..10, &42));
+        assert_eq!(map.range_containing(19), (10..20, &-1));
+        // Splitting the map without changing any data does not affect the result.
+        for _ in &mut map.iter_mut(6, 1) {}
+        assert_eq!(map.range_containing(9), (5..10, &42));
+    }
+
+    #[test]
+    fn iter_disjoint() {
+        let map = sample_map();
+        let windows = [(2, 4), (6, 1), (8, 0), (12, 8)];
+        assert_eq!(
+            map.iter_disjoint(&windows).collect::<Vec<_>>(),
+            vec![(0, 2..5, &-1), (0, 5..6, &42), (1, 6..7, &42), (3, 12..20, &-1),]
+        );
+    }
+
+    #[test]
+    fn try_for_each() {
+        let mut map = sample_map();
+        map.insert_range(12, 2, 42);
+        let mut visited = 0;
+        let first_positive = map.try_for_each(2, 18, |range, &x| {
+            visited += 1;
+            if x > 0 { ControlFlow::Break(range) } else { ControlFlow::Continue(()) }
+        });
+        assert_eq!(first_positive, ControlFlow::Break(5..10));
+        assert_eq!(visited, 2);
+        assert_eq!(
+            map.try_for_each(14, 6, |_, _| { ControlFlow::Break(()) }),
+            ControlFlow::Break(())
+        );
+        assert_eq!(
+            map.try_for_each(0, 5, |_, &x| {
+                if x > 0 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+            }),
+            ControlFlow::Continue(())
+        );
+    }
+
+    #[test]
+    fn iter_chunks() {
+        let map = sample_map();
+        let chunks: Vec<_> = map
+            .iter_chunks(3, 16, 8)
+            .map(|(chunk, runs)| (chunk, runs.collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(
+            chunks,
+            vec![
+                (3..8, vec![(3..5, &-1), (5..8, &42)]),
+                (8..16, vec![(8..10, &42), (10..16, &-1)]),
+                (16..19, vec![(16..19, &-1)]),
+            ]
+        );
+        assert_eq!(map.iter_chunks(3, 0, 8).count(), 0);
+    }
+
+    #[test]
+    fn count_runs() {
+        let mut map = sample_map();
+        map.insert_range(12, 2, 42);
+        // Split without changing anything.
+        map.insert_range(16, 2, -1);
+        let counts = |offset: u64, len: u64| {
+            let (offset, len) = (offset, len);
+            (map.count_runs(offset, len), map.count_distinct(offset, len))
+        };
+        assert_eq!(counts(0, 20), (5, 2));
+        assert_eq!(counts(14, 6), (1, 1));
+        assert_eq!(counts(6, 8), (3, 2));
+        assert_eq!(counts(6, 0), (0, 0));
+    }
+
+    #[test]
+    fn partition_point() {
+        let mut map = RangeMap::<i32>::new(20, 0);
+        map.insert_range(5, 5, 1);
+        map.insert_range(10, 10, 2);
+        let below =
+            |offset: u64, len: u64, limit: i32| map.partition_point(offset, len, |&x| x < limit);
+        assert_eq!(below(0, 20, 2), 10);
+        assert_eq!(below(12, 8, 2), 12);
+        assert_eq!(below(0, 20, 3), 20);
+        assert_eq!(below(3, 4, 1), 5);
+        assert_eq!(below(7, 0, 1), 7);
+    }
+
+    #[test]
+    fn fold() {
+        let mut map = RangeMap::<bool>::new(20, false);
+        map.insert_range(5, 5, true);
+        map.insert_range(15, 2, true);
+        let count_init =
+            |offset: u64, len: u64| {
+                map.fold(offset, len, 0, |count, range, &init| {
+                    if init { count + (range.end - range.start) } else { count }
+                })
+            };
+        assert_eq!(count_init(0, 20), 7);
+        assert_eq!(count_init(8, 8), 3);
+        assert_eq!(count_init(8, 0), 0);
+    }
+
+    #[test]
+    fn is_uniform() {
+        let mut map = sample_map();
+        assert_eq!(map.is_uniform(5, 5), Some(&42));
+        assert_eq!(map.is_uniform(4, 5), None);
+        assert_eq!(map.is_uniform(4, 0), None);
+        // Equal neighbouring elements count as uniform.
+        map.insert_range(10, 2, 42);
+        assert_eq!(map.is_uniform(6, 6), Some(&42));
+    }
+
+    #[test]
+    fn bitwise_aggregates() {
+        let mut map = RangeMap::<u8>::new(20, 0b0011);
+        map.insert_range(5, 5, 0b0110);
+        map.insert_range(10, 5, 0b1010);
+        assert_eq!(map.any_in_range(0, 5), Some(0b0011));
+        assert_eq!(map.any_in_range(3, 10), Some(0b1111));
+        assert_eq!(map.all_in_range(3, 10), Some(0b0010));
+        assert_eq!(map.all_in_range(0, 20), Some(0b0010));
+        assert_eq!(map.any_in_range(3, 0), None);
+    }
+
+    #[test]
+    fn find_range_with() {
+        let mut map = RangeMap::<bool>::new(64, true);
+        map.insert_range(0, 3, false);
+        map.insert_range(10, 10, false);
+        map.insert_range(24, 2, false);
+        fn find(map: &RangeMap<bool>, len: u64, align: u64) -> Option<u64> {
+            map.find_range_with(|&free| free, len, align)
+        }
+        assert_eq!(find(&map, 4, 1), Some(3));
+        assert_eq!(find(&map, 4, 4), Some(4));
+        assert_eq!(find(&map, 8, 1), Some(26));
+        assert_eq!(find(&map, 8, 16), Some(32));
+        // Adjacent elements satisfying the predicate are combined.
+        map.insert_range(40, 4, true);
+        assert_eq!(find(&map, 32, 32), Some(32));
+        assert_eq!(find(&map, 40, 1), None);
+    }
+
+    #[test]
+    fn iter_mut_exact() {
+        // `Cell` is not `Clone` for non-`Copy` data.
+        let mut map = RangeMap::<Cell<Vec<i32>>>::from_sorted_ranges([
+            (0..5, Cell::new(vec![0])),
+            (5..10, Cell::new(vec![1])),
+            (10..20, Cell::new(vec![2])),
+        ]);
+        for (_, x) in map.iter_mut_exact(5, 15).unwrap() {
+            x.get_mut().push(42);
+        }
+        assert_eq!(
+            map.iter_mut_exact(0, 20)
+                .unwrap()
+                .map(|(range, x)| (range, x.get_mut().clone()))
+                .collect::<Vec<_>>(),
+            vec![(0..5, vec![0]), (5..10, vec![1, 42]), (10..20, vec![2, 42])]
+        );
+        assert_eq!(map.iter_mut_exact(7, 0).unwrap().count(), 0);
+        assert_eq!(map.iter_mut_exact(3, 7).err(), Some(MisalignedRange { offset: 3 }));
+        assert_eq!(map.iter_mut_exact(5, 7).err(), Some(MisalignedRange { offset: 12 }));
+    }
+
+    #[test]
+    fn iter_mut_merging_by() {
+        // Only the first component is relevant for merging.
+        let mut map = RangeMap::<(i32, i32)>::new(20, (0, 0));
+        map.insert_range(5, 5, (0, 1));
+        map.insert_range(10, 5, (0, 2));
+        assert_eq!(map.v.len(), 4);
+        let same_first = |a: &(i32, i32), b: &(i32, i32)| a.0 == b.0;
+        for _ in map.iter_mut_merging_by(0, 20, same_first) {}
+        assert_eq!(map.v.len(), 1);
+        assert_eq!(map.get(12), &(0, 0));
+    }
+
+    #[test]
+    fn copy_within() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        map.insert_range(2, 2, 42);
+        map.insert_range(4, 2, 43);
+        // Non-overlapping copy.
+        map.copy_within(3, 10, 4);
+        assert_eq!(to_vec(&map, 8, 8), vec![-1, -1, 42, 43, 43, -1, -1, -1]);
+        // Overlapping copy to the right.
+        map.copy_within(1, 3, 5);
+        assert_eq!(to_vec(&map, 0, 9), vec![-1, -1, 42, -1, 42, 42, 43, 43, -1]);
+        // Overlapping copy to the left.
+        map.copy_within(4, 0, 5);
+        assert_eq!(to_vec(&map, 0, 9), vec![42, 42, 43, 43, -1, 42, 43, 43, -1]);
+        // Copying nothing is a NOP.
+        map.copy_within(4, 20, 0);
+    }
+
+    #[test]
+    fn copy_from() {
+        let mut src = RangeMap::<i32>::new(20, -1);
+        src.insert_range(2, 2, 42);
+        src.insert_range(4, 10, 43);
+        let mut dst = RangeMap::<i32>::new(10, 0);
+        dst.copy_from(1, &src, 3, 8);
+        assert_eq!(to_vec(&dst, 0, 10), vec![0, 42, 43, 43, 43, 43, 43, 43, 43, 0]);
+        // The large uniform source range became a single element.
+        assert_eq!(dst.v.len(), 4);
+    }
+
+    #[test]
+    fn merge_adjacent_thorough() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        map.set_merge_budget(0);
+        for offset in 0..20 {
+            for (_, x) in &mut map.iter_mut(offset, 1) {
+                *x = if offset < 10 { 42 } else { 43 };
+            }
+        }
+        assert!(map.v.len() > 2);
+        map.merge_adjacent_thorough();
+        assert_eq!(map.v.len(), 2);
+        assert_eq!(to_vec(&map, 8, 4), vec![42, 42, 43, 43]);
+    }
+
+    #[test]
+    fn u32_keys() {
+        let mut map = RangeMap::<i32, u32>::new(20, -1);
+        map.insert_range(5, 5, 42);
+        for (_, x) in &mut map.iter_mut(8, 4) {
+            *x += 1;
+        }
+        assert_eq!(
+            map.iter_all().collect::<Vec<_>>(),
+            vec![(0..5, &-1), (5..8, &42), (8..10, &43), (10..12, &0), (12..20, &-1)]
+        );
+        assert_eq!(map.range_containing(19), (12..20, &-1));
+    }
+
+    #[test]
+    fn u128_keys() {
+        let size = u128::MAX;
+        let mut map = RangeMap::<i32, u128>::new(size, -1);
+        map.insert_range(1 << 100, 1 << 90, 42);
+        assert_eq!(map.get((1 << 100) + 5), &42);
+        assert_eq!(map.range_containing(size - 1), ((1 << 100) + (1 << 90)..size, &-1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn overflowing_range() {
+        let mut map = RangeMap::<i32, u128>::new(u128::MAX, -1);
+        map.insert_range(u128::MAX - 1, 2, 42);
+    }
+
+    #[test]
+    fn from_sorted_ranges() {
+        let map: RangeMap<i32> = [(0..2, 42), (2..5, 43)].into_iter().collect();
+        assert_eq!(to_vec(&map, 0, 5), vec![42, 42, 43, 43, 43]);
+
+        let map = RangeMap::<i32>::from_sorted_ranges_with_default(
+            8,
+            [(1..2, 42), (2..4, 43), (5..6, 44)],
+            -1,
+        );
+        assert_eq!(to_vec(&map, 0, 8), vec![-1, 42, 43, 43, -1, 44, -1, -1]);
+    }
+
+    #[test]
+    fn runs() {
+        let runs = vec![(0..5, -1), (5..10, 42), (10..20, -1)];
+        let map = RangeMap::<i32>::try_from_runs(runs.clone()).unwrap();
+        assert_eq!(to_vec(&map, 4, 2), vec![-1, 42]);
+        assert_eq!(map.into_runs(), runs);
+        assert_eq!(
+            RangeMap::<i32>::try_from_runs([(0..2, 42), (3..5, 43)]).err(),
+            Some(InvalidRuns::NotContiguous { start: 3, expected: 2 })
+        );
+        assert_eq!(
+            RangeMap::<i32>::try_from_runs([(0..2, 42), (2..2, 43)]).err(),
+            Some(InvalidRuns::Empty(2..2))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_sorted_ranges_with_gap() {
+        let _ = RangeMap::<i32>::from_sorted_ranges([(0..2, 42), (3..5, 43)]);
+    }
+
+    #[test]
+    fn map() {
+        let map = sample_map();
+        let signs = map.map_ref(|_, &x| x > 0);
+        assert_eq!(
+            signs.iter_all().collect::<Vec<_>>(),
+            vec![(0..5, &false), (5..10, &true), (10..20, &false)]
+        );
+        let starts = map.map(|range, x| (range.start, x));
+        assert_eq!(to_vec(&starts, 4, 2), vec![(0, -1), (5, 42)]);
+    }
+
+    #[test]
+    fn zip_with() {
+        let mut a = RangeMap::<i32>::new(10, 0);
+        a.insert_range(2, 4, 1);
+        let mut b = RangeMap::<bool>::new(10, false);
+        b.insert_range(4, 4, true);
+        let zipped = a.zip_with(&b, |&a, &b| if b { a + 10 } else { a });
+        assert_eq!(to_vec(&zipped, 0, 10), vec![0, 0, 1, 1, 11, 11, 10, 10, 0, 0]);
+        assert_eq!(zipped.v.len(), 5);
+    }
+
+    #[test]
+    fn merge_with() {
+        let mut a = RangeMap::<i32>::new(20, 0);
+        a.insert_range(5, 10, 3);
+        let mut b = RangeMap::<i32>::new(20, 1);
+        b.insert_range(8, 10, 2);
+        let joined = a.merge_with(&b, |&a, &b| a.max(b));
+        assert_eq!(
+            joined.iter_all().collect::<Vec<_>>(),
+            vec![(0..5, &1), (5..15, &3), (15..18, &2), (18..20, &1)]
+        );
+    }
+
+    #[test]
+    fn iter_both() {
+        let mut a = RangeMap::<i32>::new(10, 0);
+        a.insert_range(2, 4, 1);
+        let mut b = RangeMap::<bool>::new(12, false);
+        b.insert_range(4, 4, true);
+        assert_eq!(
+            a.iter_both(&b, 3, 4).collect::<Vec<_>>(),
+            vec![(3..4, &1, &false), (4..6, &1, &true), (6..7, &0, &true)]
+        );
+        assert_eq!(a.iter_both(&b, 3, 0).count(), 0);
+    }
+
+    #[test]
+    fn diff() {
+        let mut a = RangeMap::<i32>::new(10, 0);
+        let mut b = a.clone();
+        assert_eq!(a.diff(&b).count(), 0);
+        a.insert_range(2, 4, 1);
+        b.insert_range(4, 4, 1);
+        // Splitting without changing the data does not affect the diff.
+        for _ in &mut b.iter_mut(1, 1) {}
+        assert_eq!(a.diff(&b).collect::<Vec<_>>(), vec![(2..4, &1, &0), (6..8, &0, &1)]);
+    }
+
+    #[test]
+    fn semantic_eq() {
+        let mut a = RangeMap::<i32>::new(20, -1);
+        a.insert_range(5, 10, 42);
+        let mut b = sample_map();
+        b.insert_range(10, 5, 42);
+        assert_ne!(a.num_runs(), b.num_runs());
+        assert!(a.semantic_eq(&b));
+        b.insert_range(14, 1, 43);
+        assert!(!a.semantic_eq(&b));
+        assert!(!a.semantic_eq(&RangeMap::new(10, -1)));
+    }
+
+    #[test]
+    fn hash_semantic() {
+        fn hash(map: &RangeMap<i32>) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            map.hash_semantic(&mut hasher);
+            hasher.finish()
+        }
+        let mut a = RangeMap::<i32>::new(20, -1);
+        a.insert_range(5, 10, 42);
+        let mut b = sample_map();
+        b.insert_range(10, 5, 42);
+        assert_eq!(hash(&a), hash(&b));
+        b.insert_range(14, 1, 43);
+        assert_ne!(hash(&a), hash(&b));
+    }
+
+    #[test]
+    fn apply_overlay() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        map.insert_range(5, 10, 42);
+        let mut patch = RangeMap::new(20, None);
+        patch.insert_range(0, 3, Some(42));
+        patch.insert_range(3, 2, Some(-1));
+        patch.insert_range(10, 8, Some(43));
+        map.apply_overlay(&patch);
+        assert_eq!(
+            map.iter_all().collect::<Vec<_>>(),
+            vec![(0..3, &42), (3..5, &-1), (5..10, &42), (10..18, &43), (18..20, &-1)]
+        );
+    }
+
+    #[test]
+    fn iter_mut_shared() {
+        let mut map = RangeMap::<Rc<Vec<i32>>>::new(10, Rc::new(vec![0]));
+        // Splitting only clones the `Rc`.
+        for _ in &mut map.iter_mut(2, 2) {}
+        assert!(Rc::ptr_eq(map.get(0), map.get(2)));
+        // Mutating clones the data of the mutated part only.
+        for (_, data) in &mut map.iter_mut_shared(2, 2) {
+            data.push(1);
+        }
+        assert_eq!(**map.get(0), vec![0]);
+        assert_eq!(**map.get(2), vec![0, 1]);
+        assert!(Rc::ptr_eq(map.get(0), map.get(4)));
+    }
+
+    #[test]
+    fn container_queries() {
+        let map = sample_map();
+        assert_eq!(map.len_bytes(), 20);
+        assert_eq!(map.bounds(), 0..20);
+        assert!(!map.is_empty());
+        assert_eq!(map.num_runs(), 3);
+
+        let empty = RangeMap::<i32>::new(0, -1);
+        assert_eq!(empty.len_bytes(), 0);
+        assert_eq!(empty.bounds(), 0..0);
+        assert!(empty.is_empty());
+        assert_eq!(empty.num_runs(), 0);
+    }
+
+    #[test]
+    fn iter_all() {
+        let mut map = sample_map();
+        assert_eq!(map.iter_all().len(), 3);
+        assert_eq!(
+            map.iter_all().rev().collect::<Vec<_>>(),
+            vec![(10..20, &-1), (5..10, &42), (0..5, &-1)]
+        );
+        let mut iter = map.iter_mut_all();
+        assert_eq!(iter.len(), 3);
+        *iter.next_back().unwrap().1 = 43;
+        assert_eq!(iter.len(), 2);
+        drop(iter);
+        assert_eq!(to_vec(&map, 8, 4), vec![42, 42, 43, 43]);
+    }
+
+    #[test]
+    fn as_runs() {
+        let map = sample_map();
+        let runs = map.as_runs();
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[1].range(), 5..10);
+        assert_eq!(*runs[1].data(), 42);
+        assert!(runs.iter().map(Elem::range).eq(map.ranges()));
+    }
+
+    #[test]
+    fn iter_nondefault() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        assert_eq!(map.iter_nondefault(&-1).count(), 0);
+        map.insert_range(5, 5, 42);
+        map.insert_range(12, 2, 43);
+        assert_eq!(map.iter_nondefault(&-1).collect::<Vec<_>>(), vec![(5..10, &42), (12..14, &43)]);
+        assert_eq!(map.iter_nondefault(&42).count(), 4);
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut map = sample_map();
+        *map.get_mut(7) += 1;
+        assert_eq!(to_vec(&map, 5, 5), vec![42, 42, 43, 42, 42]);
+        assert_eq!(map.num_runs(), 5);
+        *map.get_mut(7) -= 1;
+        assert_eq!(map.num_runs(), 3);
+        *map.get_mut(19) = 0;
+        assert_eq!(map.last_run(), Some((19..20, &0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "beyond the bounds of this RangeMap")]
+    fn out_of_range_get_mut() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        map.get_mut(20);
+    }
+
+    #[test]
+    fn copy_nonoverlapping_from() {
+        let mut map = sample_map();
+        assert_eq!(
+            map.copy_nonoverlapping_from(8, 4, 5),
+            Err(OverlapError { src: 4..9, dst: 8..13 })
+        );
+        assert_eq!(to_vec(&map, 10, 3), vec![-1, -1, -1]);
+        // Adjacent ranges and empty copies do not overlap.
+        map.copy_nonoverlapping_from(10, 5, 5).unwrap();
+        map.copy_nonoverlapping_from(3, 3, 0).unwrap();
+        assert_eq!(to_vec(&map, 4, 12), vec![-1, 42, 42, 42, 42, 42, 42, 42, 42, 42, 42, -1]);
+    }
+
+    #[test]
+    fn ranges() {
+        let mut map = sample_map();
+        map.insert_range(7, 5, 42);
+        assert_eq!(map.ranges().collect::<Vec<_>>(), vec![0..5, 5..7, 7..12, 12..20]);
+        assert_eq!(map.maximal_ranges().collect::<Vec<_>>(), vec![0..5, 5..12, 12..20]);
+        assert_eq!(
+            map.iter_maximal().collect::<Vec<_>>(),
+            vec![(0..5, &-1), (5..12, &42), (12..20, &-1)]
+        );
+    }
+
+    #[test]
+    fn formatting() {
+        let mut map = sample_map();
+        map.insert_range(7, 5, 42);
+        assert_eq!(format!("{map:?}"), "[0..5 => -1, 5..7 => 42, 7..12 => 42, 12..20 => -1]");
+        assert_eq!(format!("{map}"), "0..5 => -1, 5..12 => 42, 12..20 => -1");
+        assert_eq!(format!("{}", RangeMap::<i32>::new(0, 0)), "");
+    }
+
+    #[test]
+    fn grow_and_truncate() {
+        let mut map = RangeMap::<i32>::new(10, -1);
+        map.insert_range(4, 4, 42);
+
+        map.grow(14, 7);
+        assert_eq!(map.v.len(), 4);
+        assert_eq!(to_vec(&map, 0, 14), vec![-1, -1, -1, -1, 42, 42, 42, 42, -1, -1, 7, 7, 7, 7]);
+
+        // Truncating in the middle of an element shortens it.
+        map.truncate(6);
+        assert_eq!(map.v.len(), 2);
+        assert_eq!(to_vec(&map, 0, 6), vec![-1, -1, -1, -1, 42, 42]);
+
+        // Growing an empty map works, too.
+        map.truncate(0);
+        assert_eq!(map.v.len(), 0);
+        map.grow(3, 1);
+        assert_eq!(to_vec(&map, 0, 3), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn split_off() {
+        let mut map = RangeMap::<i32>::new(10, -1);
+        map.insert_range(4, 4, 42);
+        let tail = map.split_off(6);
+        assert_eq!(map.iter_all().collect::<Vec<_>>(), vec![(0..4, &-1), (4..6, &42)]);
+        assert_eq!(tail.iter_all().collect::<Vec<_>>(), vec![(0..2, &42), (2..4, &-1)]);
+
+        // Splitting at an element boundary or at either end of the map.
+        let mut tail = map.split_off(4);
+        assert_eq!(tail.iter_all().collect::<Vec<_>>(), vec![(0..2, &42)]);
+        assert_eq!(tail.split_off(2).iter_all().count(), 0);
+        let all = map.split_off(0);
+        assert_eq!(map.iter_all().count(), 0);
+        assert_eq!(all.iter_all().collect::<Vec<_>>(), vec![(0..4, &-1)]);
+    }
+
+    #[test]
+    fn append() {
+        let mut map = RangeMap::<i32>::new(4, -1);
+        map.insert_range(2, 2, 42);
+        let mut other = RangeMap::<i32>::new(6, -1);
+        other.insert_range(0, 3, 42);
+        map.append(other);
+        // The two elements with 42 at the seam are merged.
+        assert_eq!(
+            map.iter_all().collect::<Vec<_>>(),
+            vec![(0..2, &-1), (2..7, &42), (7..10, &-1)]
+        );
+
+        // Appending to and from empty maps.
+        let mut empty = RangeMap::<i32>::new(0, 0);
+        empty.append(RangeMap::new(0, 0));
+        assert_eq!(empty.iter_all().count(), 0);
+        empty.append(map.split_off(5));
+        assert_eq!(empty.iter_all().collect::<Vec<_>>(), vec![(0..2, &42), (2..5, &-1)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn validate_gap() {
+        let mut map = sample_map();
+        map.validate();
+        map.v[1].range.start = 6;
+        map.validate();
+    }
+
+    #[test]
+    #[should_panic]
+    fn truncate_beyond_size() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        map.truncate(21);
+    }
+
+    #[test]
+    fn try_iter() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        assert_eq!(map.try_iter(15, 5).unwrap().count(), 1);
+        assert_eq!(map.try_iter(15, 6).err(), Some(OutOfBounds { offset: 15, len: 6, size: 20 }));
+        for (_, x) in &mut map.try_iter_mut(10, 10).unwrap() {
+            *x = 42;
+        }
+        assert_eq!(to_vec(&map, 9, 2), vec![-1, 42]);
+        assert_eq!(
+            map.try_iter_mut(u64::MAX, 2).err(),
+            Some(OutOfBounds { offset: u64::MAX, len: 2, size: 20 })
+        );
+        assert_eq!(
+            map.try_insert_range(19, 2, 0),
+            Err(OutOfBounds { offset: 19, len: 2, size: 20 })
+        );
+        assert_eq!(map.try_insert_range(19, 1, 0), Ok(()));
+        assert_eq!(to_vec(&map, 18, 2), vec![42, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "beyond the bounds of this RangeMap")]
+    fn out_of_range_get() {
+        let map = RangeMap::<i32>::new(20, -1);
+        let _ = map.get(20);
+    }
+
+    #[test]
+    #[should_panic(expected = "inserting beyond the bounds of this RangeMap")]
+    fn out_of_range_insert_empty() {
+        let mut map = RangeMap::<i32>::new(0, -1);
+        map.insert_range(0, 1, 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "copying beyond the bounds of this RangeMap")]
+    fn out_of_range_copy_from_empty() {
+        let mut map = RangeMap::<i32>::new(0, -1);
+        let src = RangeMap::<i32>::new(4, 42);
+        map.copy_from(0, &src, 0, 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn out_of_range_iter_mut() {
+        let mut map = RangeMap::<i32>::new(20, -1);
+        let _ = map.iter_mut(11, 11);
+    }
+
+    #[test]
+    #[should_panic]
+    fn out_of_range_iter() {
+        let map = RangeMap::<i32>::new(20, -1);
+        let _ = map.iter(11, 11);
+    }
+}
@@ -0,0 +1,137 @@
+//! A `RangeMap` that notifies an observer about every range that is mutated, e.g. to track dirty
+//! pages or to implement write watchpoints without hooking every call site.
+
+use core::mem;
+use core::ops;
+
+use super::{IterMut, RangeKey, RangeMap, RangeSet, range_end};
+
+/// Gets notified about mutations of an `ObservedRangeMap`.
+pub trait Observer<K> {
+    /// Called with the range that is about to be mutated. The range is never empty.
+    fn on_write(&mut self, range: ops::Range<K>);
+}
+
+impl<K, F: FnMut(ops::Range<K>)> Observer<K> for F {
+    fn on_write(&mut self, range: ops::Range<K>) {
+        self(range)
+    }
+}
+
+/// Using a `RangeSet` as the observer tracks which ranges were mutated, see `take_dirty`.
+impl<K: RangeKey> Observer<K> for RangeSet<K> {
+    fn on_write(&mut self, range: ops::Range<K>) {
+        self.insert(range.start.to_offset(), (range.end - range.start).to_offset());
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ObservedRangeMap<T, K, O> {
+    map: RangeMap<T, K>,
+    observer: O,
+}
+
+impl<T, K, O> ops::Deref for ObservedRangeMap<T, K, O> {
+    type Target = RangeMap<T, K>;
+
+    fn deref(&self) -> &RangeMap<T, K> {
+        &self.map
+    }
+}
+
+impl<T, K: RangeKey, O: Observer<K>> ObservedRangeMap<T, K, O> {
+    /// Creates a new `ObservedRangeMap` for the given size, and with the given initial value used
+    /// for the entire range.
+    pub fn new(size: K::Offset, init: T, observer: O) -> ObservedRangeMap<T, K, O> {
+        ObservedRangeMap { map: RangeMap::new(size, init), observer }
+    }
+
+    /// Returns the observer.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Returns the observer mutably, e.g. to reset its state.
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Notifies the observer about a mutation of the given range, unless it is empty.
+    fn notify(&mut self, offset: K::Offset, len: K::Offset) {
+        let offset = K::from_offset(offset);
+        let len = K::from_offset(len);
+        if len != K::ZERO {
+            self.observer.on_write(offset..range_end(offset, len));
+        }
+    }
+
+    /// Provides mutable iteration over everything in the given range, see `RangeMap::iter_mut`.
+    /// The observer is notified about the entire range, whether or not anything is changed.
+    pub fn iter_mut(&mut self, offset: K::Offset, len: K::Offset) -> IterMut<'_, T, K>
+    where
+        T: Clone + PartialEq,
+    {
+        self.notify(offset, len);
+        self.map.iter_mut(offset, len)
+    }
+
+    /// Overwrites everything in the given range with `data`, see `RangeMap::insert_range`.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T)
+    where
+        T: Clone,
+    {
+        self.notify(offset, len);
+        self.map.insert_range(offset, len, data);
+    }
+
+    /// Copies data within the map, see `RangeMap::copy_within`.
+    pub fn copy_within(&mut self, src: K::Offset, dst: K::Offset, len: K::Offset)
+    where
+        T: Clone,
+    {
+        self.notify(dst, len);
+        self.map.copy_within(src, dst, len);
+    }
+}
+
+impl<T, K: RangeKey> ObservedRangeMap<T, K, RangeSet<K>> {
+    /// Returns the ranges that were mutated since the last call, coalesced into maximal ranges,
+    /// and starts tracking afresh.
+    pub fn take_dirty(&mut self) -> RangeSet<K> {
+        mem::replace(&mut self.observer, RangeSet::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifies_observer() {
+        let mut writes = Vec::new();
+        let mut map = ObservedRangeMap::<i32, u64, _>::new(20, -1, |range| writes.push(range));
+        map.insert_range(5, 5, 42);
+        for (_, x) in &mut map.iter_mut(8, 4) {
+            *x += 1;
+        }
+        map.insert_range(15, 0, 0);
+        map.copy_within(0, 10, 10);
+        assert_eq!(*map.get(16), 42);
+        drop(map);
+        assert_eq!(writes, vec![5..10, 8..12, 10..20]);
+    }
+
+    #[test]
+    fn dirty_tracking() {
+        let mut map = ObservedRangeMap::<i32, u64, _>::new(20, -1, RangeSet::new());
+        map.insert_range(5, 5, 42);
+        map.insert_range(10, 2, 43);
+        map.insert_range(15, 2, 43);
+        assert_eq!(map.take_dirty().iter().collect::<Vec<_>>(), vec![5..12, 15..17]);
+        assert!(map.take_dirty().iter().next().is_none());
+        for (_, x) in &mut map.iter_mut(0, 2) {
+            *x = 0;
+        }
+        assert_eq!(map.take_dirty().iter().collect::<Vec<_>>(), vec![0..2]);
+    }
+}
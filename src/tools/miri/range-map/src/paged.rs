@@ -0,0 +1,267 @@
+//! A variant of `RangeMap` for huge maps, e.g. covering an entire address space. The range is
+//! split into fixed-size pages, and a top-level directory associates each page either with a
+//! single value, or with a small `RangeMap` of its own. Point queries thus only search the
+//! directory and a single page, and uniform regions spanning many pages only need a single
+//! directory entry.
+
+use alloc::vec::Vec;
+use core::iter;
+use core::ops;
+
+use super::{RangeKey, RangeMap, range_end};
+
+/// The number of indices covered by a page.
+const PAGE_SIZE: u16 = 4096;
+
+#[derive(Clone, Debug)]
+enum Page<T, K> {
+    /// All indices in the page(s) are associated with the same data.
+    Uniform(T),
+    /// The data of a single page; offsets are relative to the start of the page.
+    Runs(RangeMap<T, K>),
+}
+
+#[derive(Clone, Debug)]
+struct Entry<T, K> {
+    /// The indices (not offsets!) of the pages covered by this entry. `Runs` entries always
+    /// cover exactly one page.
+    pages: ops::Range<K>,
+    page: Page<T, K>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PagedRangeMap<T, K = u64> {
+    /// The directory, sorted by page index and covering all pages of the map.
+    dir: Vec<Entry<T, K>>,
+    /// The size of the range covered by this map.
+    size: K,
+}
+
+impl<T, K> PagedRangeMap<T, K>
+where
+    T: Clone + PartialEq,
+    K: RangeKey + From<u16> + ops::Mul<Output = K> + ops::Div<Output = K>,
+{
+    /// Creates a new `PagedRangeMap` for the given size, and with the given initial value used
+    /// for the entire range.
+    pub fn new(size: K::Offset, init: T) -> PagedRangeMap<T, K> {
+        let size = K::from_offset(size);
+        let mut dir = Vec::new();
+        if size > K::ZERO {
+            dir.push(Entry { pages: K::ZERO..Self::num_pages(size), page: Page::Uniform(init) });
+        }
+        PagedRangeMap { dir, size }
+    }
+
+    fn page_size() -> K {
+        K::from(PAGE_SIZE)
+    }
+
+    /// The number of pages needed to cover `size` indices.
+    fn num_pages(size: K) -> K {
+        let full = size / Self::page_size();
+        if full * Self::page_size() == size { full } else { full + K::ONE }
+    }
+
+    /// Returns the range of indices covered by the given page. Only the last page can be shorter
+    /// than `PAGE_SIZE`.
+    fn page_range(&self, page: K) -> ops::Range<K> {
+        let start = page * Self::page_size();
+        let end = if self.size - start > Self::page_size() {
+            start + Self::page_size()
+        } else {
+            self.size
+        };
+        start..end
+    }
+
+    /// Finds the index of the directory entry containing the given page.
+    fn find_entry(&self, page: K) -> usize {
+        self.dir.partition_point(|entry| entry.pages.end <= page)
+    }
+
+    /// Splits the directory entry containing the given page, such that the 2nd one starts there.
+    fn split_dir(&mut self, page: K) {
+        let idx = self.find_entry(page);
+        let Some(entry) = self.dir.get_mut(idx) else { return };
+        if entry.pages.start < page {
+            let Page::Uniform(data) = &entry.page else {
+                unreachable!("`Runs` entries only cover a single page")
+            };
+            let second = Entry { pages: page..entry.pages.end, page: Page::Uniform(data.clone()) };
+            entry.pages.end = page;
+            self.dir.insert(idx + 1, second);
+        }
+    }
+
+    /// Merges the entry at the given index with its neighbours, if they are uniform with the
+    /// same data.
+    fn merge_with_neighbours(&mut self, mut idx: usize) {
+        let mergeable = |a: &Entry<T, K>, b: &Entry<T, K>| {
+            match (&a.page, &b.page) {
+                (Page::Uniform(a), Page::Uniform(b)) => a == b,
+                _ => false,
+            }
+        };
+        if idx > 0 && mergeable(&self.dir[idx - 1], &self.dir[idx]) {
+            self.dir[idx - 1].pages.end = self.dir[idx].pages.end;
+            self.dir.remove(idx);
+            idx -= 1;
+        }
+        if idx + 1 < self.dir.len() && mergeable(&self.dir[idx], &self.dir[idx + 1]) {
+            self.dir[idx].pages.end = self.dir[idx + 1].pages.end;
+            self.dir.remove(idx + 1);
+        }
+    }
+
+    /// Returns the data associated with the given offset.
+    pub fn get(&self, offset: K::Offset) -> &T {
+        let offset = K::from_offset(offset);
+        assert!(offset < self.size, "getting an offset beyond the bounds of this RangeMap");
+        let page = offset / Self::page_size();
+        match &self.dir[self.find_entry(page)].page {
+            Page::Uniform(data) => data,
+            Page::Runs(map) => map.get((offset - self.page_range(page).start).to_offset()),
+        }
+    }
+
+    /// Provides read-only iteration over everything in the given range. The provided ranges are
+    /// clipped to the given range. Ranges are split at least at every page boundary where the
+    /// page is not uniform.
+    pub fn iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        let offset = K::from_offset(offset);
+        let end = range_end(offset, K::from_offset(len));
+        assert!(end <= self.size, "iterating beyond the bounds of this RangeMap");
+        let entries = if offset == end {
+            &[]
+        } else {
+            &self.dir[self.find_entry(offset / Self::page_size())..]
+        };
+        entries
+            .iter()
+            .take_while(move |entry| entry.pages.start * Self::page_size() < end)
+            .flat_map(move |entry| {
+                let start = self.page_range(entry.pages.start).start.max(offset);
+                let entry_end = self.page_range(entry.pages.end - K::ONE).end.min(end);
+                let (uniform, runs) = match &entry.page {
+                    Page::Uniform(data) => (Some((start..entry_end, data)), None),
+                    Page::Runs(map) => {
+                        let base = self.page_range(entry.pages.start).start;
+                        let runs = map
+                            .iter_clipped(
+                                (start - base).to_offset(),
+                                (entry_end - start).to_offset(),
+                            )
+                            .map(move |(range, data)| (range.start + base..range.end + base, data));
+                        (None, Some(runs))
+                    }
+                };
+                uniform.into_iter().chain(runs.into_iter().flatten())
+            })
+    }
+
+    /// Overwrites everything in the given range with `data`. Pages that are entirely covered
+    /// become uniform; the others get their own `RangeMap`.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T) {
+        let offset = K::from_offset(offset);
+        let end = range_end(offset, K::from_offset(len));
+        assert!(end <= self.size, "inserting beyond the bounds of this RangeMap");
+        let mut pos = offset;
+        while pos < end {
+            let page = pos / Self::page_size();
+            let page_range = self.page_range(page);
+            if pos == page_range.start && page_range.end <= end {
+                // A run of entirely covered pages.
+                let pages_end = if end == self.size {
+                    Self::num_pages(self.size)
+                } else {
+                    end / Self::page_size()
+                };
+                self.set_uniform(page..pages_end, data.clone());
+                pos = self.page_range(pages_end - K::ONE).end;
+            } else {
+                let chunk_end = page_range.end.min(end);
+                self.insert_into_page(page, pos..chunk_end, data.clone());
+                pos = chunk_end;
+            }
+        }
+    }
+
+    /// Makes the given pages uniform with the given data.
+    fn set_uniform(&mut self, pages: ops::Range<K>, data: T) {
+        self.split_dir(pages.start);
+        self.split_dir(pages.end);
+        let first = self.find_entry(pages.start);
+        let last = self.dir.partition_point(|entry| entry.pages.start < pages.end);
+        self.dir.splice(first..last, iter::once(Entry { pages, page: Page::Uniform(data) }));
+        self.merge_with_neighbours(first);
+    }
+
+    /// Overwrites the given range, which must be within the given page, with `data`.
+    fn insert_into_page(&mut self, page: K, range: ops::Range<K>, data: T) {
+        let base = self.page_range(page).start;
+        let page_len = self.page_range(page).end - base;
+        self.split_dir(page);
+        self.split_dir(page + K::ONE);
+        let idx = self.find_entry(page);
+        let entry = &mut self.dir[idx];
+        if let Page::Uniform(old) = &entry.page {
+            entry.page = Page::Runs(RangeMap::new(page_len.to_offset(), old.clone()));
+        }
+        let Page::Runs(map) = &mut entry.page else { unreachable!() };
+        map.insert_range(
+            (range.start - base).to_offset(),
+            (range.end - range.start).to_offset(),
+            data,
+        );
+        // If the page became uniform, we do not need its `RangeMap` any more.
+        if let Some(data) = map.is_uniform(K::ZERO.to_offset(), page_len.to_offset()) {
+            entry.page = Page::Uniform(data.clone());
+            self.merge_with_neighbours(idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::read_range;
+    use super::*;
+
+    #[test]
+    fn insert_range() {
+        let page = u64::from(PAGE_SIZE);
+        let mut map = PagedRangeMap::<i32>::new(1u64 << 48, -1);
+        // Within a single page.
+        map.insert_range(10, 2, 42);
+        assert_eq!(map.dir.len(), 2);
+        assert_eq!(read_range(9, 4, |i| *map.get(i)), vec![-1, 42, 42, -1]);
+        // Spanning several pages, partially covering the first and last one.
+        map.insert_range(page - 1, 3 * page, 43);
+        assert_eq!(map.dir.len(), 4);
+        assert_eq!(read_range(page - 2, 3, |i| *map.get(i)), vec![-1, 43, 43]);
+        assert_eq!(read_range(4 * page - 2, 3, |i| *map.get(i)), vec![43, -1, -1]);
+        assert_eq!(
+            map.iter(8, 4 * page).collect::<Vec<_>>(),
+            vec![
+                (8..10, &-1),
+                (10..12, &42),
+                (12..page - 1, &-1),
+                (page - 1..page, &43),
+                (page..3 * page, &43),
+                (3 * page..4 * page - 1, &43),
+                (4 * page - 1..4 * page, &-1),
+                (4 * page..4 * page + 8, &-1),
+            ]
+        );
+
+        // Covering the partial pages again makes them uniform.
+        map.insert_range(0, page, 43);
+        map.insert_range(3 * page, page, 43);
+        assert_eq!(map.dir.len(), 2);
+        assert_eq!(read_range(4 * page - 1, 2, |i| *map.get(i)), vec![43, -1]);
+    }
+}
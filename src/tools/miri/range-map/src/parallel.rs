@@ -0,0 +1,43 @@
+//! Parallel iteration over a `RangeMap`, enabled by the `rayon` feature.
+
+use core::ops;
+
+use rayon::prelude::*;
+
+use super::{RangeKey, RangeMap};
+
+impl<T: Sync, K: RangeKey + Send + Sync> RangeMap<T, K> {
+    /// Like `iter`, but provides the elements as a parallel iterator.
+    pub fn par_iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl IndexedParallelIterator<Item = (ops::Range<K>, &T)> {
+        self.elems_in(K::from_offset(offset), K::from_offset(len))
+            .par_iter()
+            .map(|elem| (elem.range.clone(), &elem.data))
+    }
+}
+
+impl<T: Send, K: RangeKey + Send + Sync> RangeMap<T, K> {
+    /// Like `iter_mut_all`, but provides the elements as a parallel iterator.
+    pub fn par_iter_mut_all(
+        &mut self,
+    ) -> impl IndexedParallelIterator<Item = (ops::Range<K>, &mut T)> {
+        self.v.par_iter_mut().map(|elem| (elem.range.clone(), &mut elem.data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::sample_map;
+    use super::*;
+
+    #[test]
+    fn par_iter() {
+        let mut map = sample_map();
+        map.par_iter_mut_all().for_each(|(range, x)| *x += i32::try_from(range.start).unwrap());
+        assert_eq!(map.par_iter(3, 5).collect::<Vec<_>>(), vec![(0..5, &-1), (5..10, &47)]);
+        assert_eq!(map.par_iter(3, 0).count(), 0);
+    }
+}
@@ -0,0 +1,156 @@
+//! A collection of `RangeMap`s, one per allocation, with bulk operations across all of them.
+//! The allocations are identified by any ordered ID type (in Miri, `AllocId`), and are kept in
+//! ID order so that iteration is deterministic.
+
+use alloc::collections::{BTreeMap, btree_map};
+use core::ops;
+
+use super::{RangeKey, RangeMap, RangeMapStats};
+
+#[derive(Clone, Debug)]
+pub struct AllocRangeMap<I, T, K = u64> {
+    maps: BTreeMap<I, RangeMap<T, K>>,
+}
+
+impl<I, T, K> Default for AllocRangeMap<I, T, K> {
+    fn default() -> Self {
+        AllocRangeMap { maps: BTreeMap::new() }
+    }
+}
+
+impl<I: Ord + Copy, T, K: RangeKey> AllocRangeMap<I, T, K> {
+    /// Creates a new `AllocRangeMap` without any allocations.
+    pub fn new() -> AllocRangeMap<I, T, K> {
+        AllocRangeMap::default()
+    }
+
+    /// Adds a map for a new allocation of the given size, with the given initial value used for
+    /// the entire allocation. Panics if there already is a map for that allocation.
+    pub fn add_alloc(&mut self, id: I, size: K::Offset, init: T) -> &mut RangeMap<T, K> {
+        match self.maps.entry(id) {
+            btree_map::Entry::Vacant(entry) => entry.insert(RangeMap::new(size, init)),
+            btree_map::Entry::Occupied(_) =>
+                panic!("adding an allocation that already has a RangeMap"),
+        }
+    }
+
+    /// Removes the map of the given allocation and returns it, if there is one.
+    pub fn remove_alloc(&mut self, id: I) -> Option<RangeMap<T, K>> {
+        self.maps.remove(&id)
+    }
+
+    /// Removes the maps of all allocations.
+    pub fn clear(&mut self) {
+        self.maps.clear();
+    }
+
+    /// Returns the number of allocations that have a map.
+    pub fn num_allocs(&self) -> usize {
+        self.maps.len()
+    }
+
+    /// Returns the map of the given allocation, if there is one.
+    pub fn alloc(&self, id: I) -> Option<&RangeMap<T, K>> {
+        self.maps.get(&id)
+    }
+
+    /// Returns the map of the given allocation mutably, if there is one.
+    pub fn alloc_mut(&mut self, id: I) -> Option<&mut RangeMap<T, K>> {
+        self.maps.get_mut(&id)
+    }
+
+    /// Returns the data associated with the given offset in the given allocation. Panics if
+    /// there is no map for that allocation.
+    pub fn get(&self, id: I, offset: K::Offset) -> &T {
+        self.alloc(id).expect("getting an offset in an allocation without a RangeMap").get(offset)
+    }
+
+    /// Provides iteration over the maps of all allocations, in ID order.
+    pub fn iter_allocs(&self) -> impl Iterator<Item = (I, &RangeMap<T, K>)> {
+        self.maps.iter().map(|(&id, map)| (id, map))
+    }
+
+    /// Provides iteration over all maximal ranges, across all allocations, whose data differs
+    /// from `clean`. Allocations are visited in ID order.
+    pub fn iter_dirty<'a>(
+        &'a self,
+        clean: &'a T,
+    ) -> impl Iterator<Item = (I, ops::Range<K>, &'a T)> + 'a
+    where
+        T: PartialEq,
+    {
+        self.maps.iter().flat_map(move |(&id, map)| {
+            map.iter_maximal()
+                .filter(move |(_, data)| *data != clean)
+                .map(move |(range, data)| (id, range, data))
+        })
+    }
+
+    /// Returns the statistics of all allocations combined, see `RangeMap::stats`.
+    pub fn stats(&self) -> RangeMapStats<K>
+    where
+        K: ops::Div<Output = K> + TryFrom<usize>,
+    {
+        let mut total = RangeMapStats {
+            runs: 0,
+            average_run_len: K::ZERO,
+            largest_run: K::ZERO,
+            heap_bytes: 0,
+        };
+        let mut total_size = K::ZERO;
+        for map in self.maps.values() {
+            let stats = map.stats();
+            total.runs += stats.runs;
+            total.largest_run = total.largest_run.max(stats.largest_run);
+            total.heap_bytes += stats.heap_bytes;
+            total_size = total_size
+                .checked_add(map.size())
+                .expect("the total size of all allocations overflows the offset type");
+        }
+        if let Ok(runs) = K::try_from(total.runs) {
+            if runs > K::ZERO {
+                total.average_run_len = total_size / runs;
+            }
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations() {
+        let mut maps = AllocRangeMap::<u32, i32>::new();
+        maps.add_alloc(2, 20, 0).insert_range(5, 5, 42);
+        maps.add_alloc(1, 10, 43);
+        assert_eq!(maps.num_allocs(), 2);
+        assert_eq!(*maps.get(2, 6), 42);
+        assert_eq!(maps.iter_dirty(&0).collect::<Vec<_>>(), vec![(1, 0..10, &43), (2, 5..10, &42)]);
+        let stats = maps.stats();
+        assert_eq!((stats.runs, stats.average_run_len, stats.largest_run), (4, 7, 10));
+        assert_eq!(stats.heap_bytes, maps.alloc(2).unwrap().stats().heap_bytes);
+        assert_eq!(maps.remove_alloc(1).unwrap().num_runs(), 1);
+        assert!(maps.alloc(1).is_none());
+        maps.clear();
+        assert_eq!(maps.num_allocs(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "already has a RangeMap")]
+    fn duplicate_alloc() {
+        let mut maps = AllocRangeMap::<u32, i32>::new();
+        maps.add_alloc(1, 10, 0);
+        maps.add_alloc(1, 10, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflows the offset type")]
+    fn stats_overflow() {
+        let mut maps = AllocRangeMap::<u32, i32, u32>::new();
+        maps.add_alloc(1, u32::MAX, 0);
+        maps.add_alloc(2, 1, 0);
+        maps.stats();
+    }
+}
@@ -0,0 +1,187 @@
+//! An immutable variant of `RangeMap`, where mutations return a new map instead of changing the
+//! existing one. The elements are stored in chunks that are shared between the old and the new
+//! map, so only the chunks touched by a mutation need to be copied. This makes it cheap to keep
+//! many slightly different maps alive at the same time.
+
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::iter;
+use core::ops;
+
+use super::{Elem, RangeKey, range_end};
+
+/// The maximal number of elements in a newly created chunk.
+const CHUNK_LEN: usize = 32;
+
+#[derive(Debug)]
+pub struct PersistentRangeMap<T, K = u64> {
+    /// The elements, split into chunks. The chunks are never empty, and together they cover the
+    /// entire range of the map like the elements of a `RangeMap`.
+    chunks: Vec<Rc<Vec<Elem<T, K>>>>,
+}
+
+// Cloning the map only clones the list of chunks, not the chunks themselves, so this does not
+// need `T: Clone`.
+impl<T, K> Clone for PersistentRangeMap<T, K> {
+    fn clone(&self) -> Self {
+        PersistentRangeMap { chunks: self.chunks.clone() }
+    }
+}
+
+impl<T, K: RangeKey> PersistentRangeMap<T, K> {
+    /// Creates a new `PersistentRangeMap` for the given size, and with the given initial value
+    /// used for the entire range.
+    pub fn new(size: K::Offset, init: T) -> PersistentRangeMap<T, K> {
+        let size = K::from_offset(size);
+        let mut chunks = Vec::new();
+        if size > K::ZERO {
+            chunks.push(Rc::new(alloc::vec![Elem { range: K::ZERO..size, data: init }]));
+        }
+        PersistentRangeMap { chunks }
+    }
+
+    fn size(&self) -> K {
+        self.chunks.last().map_or(K::ZERO, |chunk| chunk.last().unwrap().range.end)
+    }
+
+    /// Finds the index of the chunk containing the given offset.
+    fn find_chunk(&self, offset: K) -> usize {
+        debug_assert!(offset < self.size());
+        self.chunks.partition_point(|chunk| chunk[0].range.start <= offset) - 1
+    }
+
+    /// Finds the element containing the given offset.
+    fn find_elem(&self, offset: K) -> &Elem<T, K> {
+        let chunk = &self.chunks[self.find_chunk(offset)];
+        &chunk[chunk.partition_point(|elem| elem.range.end <= offset)]
+    }
+
+    /// Returns the data associated with the given offset.
+    pub fn get(&self, offset: K::Offset) -> &T {
+        let offset = K::from_offset(offset);
+        assert!(offset < self.size(), "getting an offset beyond the bounds of this RangeMap");
+        &self.find_elem(offset).data
+    }
+
+    /// Provides read-only iteration over everything in the given range, see `RangeMap::iter`.
+    pub fn iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        let offset = K::from_offset(offset);
+        let end = range_end(offset, K::from_offset(len));
+        assert!(end <= self.size(), "iterating beyond the bounds of this RangeMap");
+        let chunks = if offset == end { &[] } else { &self.chunks[self.find_chunk(offset)..] };
+        chunks
+            .iter()
+            .flat_map(|chunk| chunk.iter())
+            .skip_while(move |elem| elem.range.end <= offset)
+            .take_while(move |elem| elem.range.start < end)
+            .map(|elem| (elem.range.clone(), &elem.data))
+    }
+
+    /// Returns a new map where everything in the given range is associated with `data`, and
+    /// everything else is the same as in `self`. Only the chunks overlapping the range are
+    /// copied; all other chunks are shared between `self` and the new map. Like in a `RangeMap`,
+    /// the new element is merged with its neighbours if they carry equal data.
+    #[must_use]
+    pub fn insert_range(&self, offset: K::Offset, len: K::Offset, data: T) -> Self
+    where
+        T: Clone + PartialEq,
+    {
+        let mut offset = K::from_offset(offset);
+        let mut end = range_end(offset, K::from_offset(len));
+        assert!(end <= self.size(), "inserting beyond the bounds of this RangeMap");
+        if offset == end {
+            // Nothing to overwrite.
+            return self.clone();
+        }
+        // Absorb the elements right before and after the range if their data is equal. These are
+        // either the parts of the touched elements outside the range, or the neighbouring
+        // elements if the range starts or ends at an element boundary.
+        if offset > K::ZERO {
+            let before = self.find_elem(offset - K::ONE);
+            if before.data == data {
+                offset = before.range.start;
+            }
+        }
+        if end < self.size() {
+            let after = self.find_elem(end);
+            if after.data == data {
+                end = after.range.end;
+            }
+        }
+        let first_chunk = self.find_chunk(offset);
+        let last_chunk = self.find_chunk(end - K::ONE);
+        let touched =
+            || self.chunks[first_chunk..=last_chunk].iter().flat_map(|chunk| chunk.iter());
+        // Keep the parts of the touched elements that are outside the range.
+        let head = touched().filter(|elem| elem.range.start < offset).map(|elem| {
+            Elem { range: elem.range.start..elem.range.end.min(offset), data: elem.data.clone() }
+        });
+        let tail = touched().filter(|elem| elem.range.end > end).map(|elem| {
+            Elem { range: elem.range.start.max(end)..elem.range.end, data: elem.data.clone() }
+        });
+        let mut elems = head.chain(iter::once(Elem { range: offset..end, data })).chain(tail);
+        let new_chunks = iter::from_fn(|| {
+            let chunk: Vec<_> = elems.by_ref().take(CHUNK_LEN).collect();
+            (!chunk.is_empty()).then(|| Rc::new(chunk))
+        });
+        let chunks = self.chunks[..first_chunk]
+            .iter()
+            .cloned()
+            .chain(new_chunks)
+            .chain(self.chunks[last_chunk + 1..].iter().cloned())
+            .collect();
+        PersistentRangeMap { chunks }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::read_range;
+    use super::*;
+
+    #[test]
+    fn insert_range() {
+        let empty = PersistentRangeMap::<i32>::new(16, 0);
+        let a = empty.insert_range(4, 6, 1);
+        let b = a.insert_range(8, 4, 2);
+        assert_eq!(read_range(0, 16, |i| *empty.get(i)), vec![0; 16]);
+        assert_eq!(read_range(3, 8, |i| *a.get(i)), vec![0, 1, 1, 1, 1, 1, 1, 0]);
+        assert_eq!(read_range(3, 10, |i| *b.get(i)), vec![0, 1, 1, 1, 1, 2, 2, 2, 2, 0]);
+        assert_eq!(b.iter(6, 4).collect::<Vec<_>>(), vec![(4..8, &1), (8..12, &2)]);
+        assert_eq!(b.iter(6, 0).count(), 0);
+    }
+
+    #[test]
+    fn insert_range_merges() {
+        let map = PersistentRangeMap::<i32>::new(20, -1);
+        // Equal to the element being split.
+        let a = map.insert_range(5, 5, -1);
+        assert_eq!(a.iter(0, 20).count(), 1);
+        // Equal to the neighbours at an element boundary.
+        let b = map.insert_range(5, 5, 42);
+        let c = b.insert_range(0, 5, 42).insert_range(10, 2, 42);
+        assert_eq!(c.iter(0, 20).collect::<Vec<_>>(), vec![(0..12, &42), (12..20, &-1)]);
+        // Equal to the parts of the touched elements outside the range.
+        let d = c.insert_range(3, 12, 42);
+        assert_eq!(d.iter(0, 20).collect::<Vec<_>>(), vec![(0..15, &42), (15..20, &-1)]);
+    }
+
+    #[test]
+    fn sharing() {
+        let mut map = PersistentRangeMap::<i32>::new(1000, -1);
+        for i in 0..100u64 {
+            map = map.insert_range(10 * i, 5, i32::try_from(i).unwrap());
+        }
+        assert!(map.chunks.len() > 2);
+        // Only the chunk containing the mutated range gets copied.
+        let new = map.insert_range(12, 1, 0);
+        assert!(!Rc::ptr_eq(&map.chunks[0], &new.chunks[0]));
+        assert!(Rc::ptr_eq(map.chunks.last().unwrap(), new.chunks.last().unwrap()));
+        assert_eq!(read_range(10, 4, |i| *map.get(i)), vec![1, 1, 1, 1]);
+        assert_eq!(read_range(10, 4, |i| *new.get(i)), vec![1, 1, 0, 1]);
+    }
+}
@@ -0,0 +1,133 @@
+//! A `RangeMap` over a circular domain, e.g. for ring buffers: ranges passed to it may wrap
+//! around the end of the map back to offset 0.
+
+use core::iter;
+use core::ops;
+
+use super::{ByRange, IterMut, RangeKey, RangeMap};
+
+#[derive(Clone, Debug)]
+pub struct RingRangeMap<T, K = u64> {
+    map: RangeMap<T, K>,
+}
+
+impl<T, K> ops::Deref for RingRangeMap<T, K> {
+    type Target = RangeMap<T, K>;
+
+    fn deref(&self) -> &RangeMap<T, K> {
+        &self.map
+    }
+}
+
+impl<T, K: RangeKey> RingRangeMap<T, K> {
+    /// Creates a new `RingRangeMap` for the given size, and with the given initial value used for
+    /// the entire range.
+    pub fn new(size: K::Offset, init: T) -> RingRangeMap<T, K> {
+        RingRangeMap { map: RangeMap::new(size, init) }
+    }
+
+    /// Splits the given range into the part up to the end of the map and the part that wraps
+    /// around to offset 0, as `(offset, len)` pairs. The second part is empty if the range does
+    /// not wrap.
+    fn split(&self, offset: K::Offset, len: K::Offset) -> [(K, K); 2] {
+        let offset = K::from_offset(offset);
+        let len = K::from_offset(len);
+        let size = self.map.size();
+        assert!(len <= size, "the range of length {len:?} is longer than this RingRangeMap");
+        if len == K::ZERO {
+            return [(K::ZERO, K::ZERO); 2];
+        }
+        assert!(offset < size, "offset {offset:?} is out-of-bounds of this RingRangeMap");
+        let first = (size - offset).min(len);
+        [(offset, first), (K::ZERO, len - first)]
+    }
+
+    /// Provides read-only iteration over everything in the given range, which may wrap around
+    /// the end of the map. Elements are yielded in the order of the range, so after wrapping,
+    /// the offsets start over at 0.
+    pub fn iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        let [(offset, len), (wrapped_offset, wrapped_len)] = self.split(offset, len);
+        self.map
+            .iter(offset.to_offset(), len.to_offset())
+            .chain(self.map.iter(wrapped_offset.to_offset(), wrapped_len.to_offset()))
+    }
+
+    /// Provides mutable iteration over everything in the given range, which may wrap around the
+    /// end of the map, see `RangeMap::iter_mut`. Like `iter`, elements are yielded in the order
+    /// of the range.
+    pub fn iter_mut(&mut self, offset: K::Offset, len: K::Offset) -> RingIterMut<'_, T, K>
+    where
+        T: Clone + PartialEq,
+    {
+        let [(offset, len), (wrapped_offset, wrapped_len)] = self.split(offset, len);
+        // `iter_mut_multi` needs the ranges sorted, so the wrapped part comes first there.
+        let ranges = [
+            (wrapped_offset.to_offset(), wrapped_len.to_offset()),
+            (offset.to_offset(), len.to_offset()),
+        ];
+        RingIterMut(self.map.iter_mut_multi(&ranges))
+    }
+
+    /// Overwrites everything in the given range, which may wrap around the end of the map, with
+    /// `data`.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T)
+    where
+        T: Clone,
+    {
+        let [(offset, len), (wrapped_offset, wrapped_len)] = self.split(offset, len);
+        self.map.insert_range(wrapped_offset.to_offset(), wrapped_len.to_offset(), data.clone());
+        self.map.insert_range(offset.to_offset(), len.to_offset(), data);
+    }
+}
+
+/// Mutable access to a range of a `RingRangeMap`, see `RingRangeMap::iter_mut`. Like `IterMut`,
+/// the elements are accessed by iterating over a mutable reference to it.
+pub struct RingIterMut<'a, T: PartialEq, K: RangeKey = u64>(IterMut<'a, T, K>);
+
+impl<'g, T: PartialEq, K: RangeKey> IntoIterator for &'g mut RingIterMut<'_, T, K> {
+    type Item = (ops::Range<K>, &'g mut T);
+    type IntoIter = iter::Flatten<iter::Rev<ByRange<'g, T, K>>>;
+
+    fn into_iter(self) -> iter::Flatten<iter::Rev<ByRange<'g, T, K>>> {
+        // The wrapped part comes first in the map, but last in the range.
+        self.0.by_range().rev().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrapping() {
+        let mut map = RingRangeMap::<i32>::new(20, -1);
+        map.insert_range(16, 8, 42);
+        assert_eq!(
+            map.iter_all().collect::<Vec<_>>(),
+            vec![(0..4, &42), (4..16, &-1), (16..20, &42)]
+        );
+        let mut ranges = Vec::new();
+        for (range, x) in &mut map.iter_mut(18, 4) {
+            ranges.push(range);
+            *x += 1;
+        }
+        assert_eq!(ranges, vec![18..20, 0..2]);
+        assert_eq!(
+            map.iter(17, 6).collect::<Vec<_>>(),
+            vec![(16..18, &42), (18..20, &43), (0..2, &43), (2..4, &42)]
+        );
+        // Ranges that do not wrap work just like in a `RangeMap`.
+        assert_eq!(map.iter(5, 2).count(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "longer than this RingRangeMap")]
+    fn too_long() {
+        let map = RingRangeMap::<i32>::new(20, -1);
+        map.iter(5, 21).for_each(drop);
+    }
+}
@@ -0,0 +1,50 @@
+//! Serialization support for `RangeMap`, enabled by the `serde` feature.
+//! A map is represented as a list of `(start, end, data)` triples, one for each element.
+
+use alloc::vec::Vec;
+
+use serde::de::{Deserialize, Deserializer, Error as _};
+use serde::ser::{Serialize, Serializer};
+
+use super::{RangeKey, RangeMap};
+
+impl<T: Serialize, K: RangeKey + Serialize> Serialize for RangeMap<T, K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer
+            .collect_seq(self.v.iter().map(|elem| (elem.range.start, elem.range.end, &elem.data)))
+    }
+}
+
+impl<'de, T: Deserialize<'de>, K: RangeKey + Deserialize<'de>> Deserialize<'de> for RangeMap<T, K> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let triples = Vec::<(K, K, T)>::deserialize(deserializer)?;
+        RangeMap::try_from_runs(triples.into_iter().map(|(start, end, data)| (start..end, data)))
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::sample_map;
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let map = sample_map();
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(json, "[[0,5,-1],[5,10,42],[10,20,-1]]");
+        let parsed: RangeMap<i32> = serde_json::from_str(&json).unwrap();
+        assert!(parsed.semantic_eq(&map));
+    }
+
+    #[test]
+    fn reject_invalid_triples() {
+        let parse = |json| serde_json::from_str::<RangeMap<i32>>(json);
+        // Backwards.
+        assert!(parse("[[0,5,-1],[5,3,42]]").is_err());
+        // Not contiguous.
+        assert!(parse("[[0,5,-1],[6,10,42]]").is_err());
+        // Not starting at 0.
+        assert!(parse("[[1,5,-1]]").is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Implements a set of integer indices, stored as a list of maximal ranges. This is like a
+//! `RangeMap<bool>`, but without storing any data and without the need to merge equal neighbours.
+
+use alloc::vec::Vec;
+use core::iter;
+use core::ops;
+
+use super::{RangeKey, range_end};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangeSet<K = u64> {
+    /// The ranges in this set. They are sorted, non-empty, and neither overlap nor touch each
+    /// other, so every range is maximal.
+    v: Vec<ops::Range<K>>,
+}
+
+impl<K: RangeKey> RangeSet<K> {
+    /// Creates a new, empty `RangeSet`.
+    pub fn new() -> RangeSet<K> {
+        RangeSet { v: Vec::new() }
+    }
+
+    /// Appends `range` to `v`, whose ranges must not start after `range`. If the last range in `v`
+    /// overlaps or touches `range`, the two are merged, to keep the ranges maximal.
+    fn push(v: &mut Vec<ops::Range<K>>, range: ops::Range<K>) {
+        match v.last_mut() {
+            Some(last) if last.end >= range.start => last.end = last.end.max(range.end),
+            _ => v.push(range),
+        }
+    }
+
+    /// Adds everything in the given range to the set.
+    pub fn insert(&mut self, offset: K::Offset, len: K::Offset) {
+        let start = K::from_offset(offset);
+        let end = range_end(start, K::from_offset(len));
+        if start == end {
+            return;
+        }
+        // Everything in `first..last` overlaps or touches the new range.
+        let first = self.v.partition_point(|range| range.end < start);
+        let last = self.v.partition_point(|range| range.start <= end);
+        let (start, end) = if first < last {
+            (start.min(self.v[first].start), end.max(self.v[last - 1].end))
+        } else {
+            (start, end)
+        };
+        self.v.splice(first..last, iter::once(start..end));
+    }
+
+    /// Removes everything in the given range from the set.
+    pub fn remove(&mut self, offset: K::Offset, len: K::Offset) {
+        let start = K::from_offset(offset);
+        let end = range_end(start, K::from_offset(len));
+        if start == end {
+            return;
+        }
+        // Everything in `first..last` overlaps the removed range.
+        let first = self.v.partition_point(|range| range.end <= start);
+        let last = self.v.partition_point(|range| range.start < end);
+        if first == last {
+            return;
+        }
+        // Keep the parts sticking out at either end.
+        let head = self.v[first].start..start;
+        let tail = end..self.v[last - 1].end;
+        let kept = [head, tail].into_iter().filter(|range| range.start < range.end);
+        self.v.splice(first..last, kept);
+    }
+
+    /// Returns whether everything in the given range is in the set. This is trivially true for
+    /// empty ranges.
+    pub fn contains_range(&self, offset: K::Offset, len: K::Offset) -> bool {
+        let start = K::from_offset(offset);
+        let end = range_end(start, K::from_offset(len));
+        if start == end {
+            return true;
+        }
+        let idx = self.v.partition_point(|range| range.end <= start);
+        self.v.get(idx).is_some_and(|range| range.start <= start && end <= range.end)
+    }
+
+    /// Returns whether anything in the given range is in the set. This is trivially false for
+    /// empty ranges.
+    pub fn overlaps(&self, offset: K::Offset, len: K::Offset) -> bool {
+        let start = K::from_offset(offset);
+        let end = range_end(start, K::from_offset(len));
+        if start == end {
+            return false;
+        }
+        let idx = self.v.partition_point(|range| range.end <= start);
+        self.v.get(idx).is_some_and(|range| range.start < end)
+    }
+
+    /// Provides iteration over the maximal ranges in this set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = ops::Range<K>> + '_ {
+        self.v.iter().cloned()
+    }
+
+    /// Returns the set of everything in `self` or `other`.
+    #[must_use]
+    pub fn union(&self, other: &RangeSet<K>) -> RangeSet<K> {
+        let mut v = Vec::with_capacity(self.v.len() + other.v.len());
+        let (mut a, mut b) = (self.v.iter().peekable(), other.v.iter().peekable());
+        loop {
+            let next = match (a.peek(), b.peek()) {
+                (Some(ra), Some(rb)) =>
+                    if ra.start <= rb.start {
+                        a.next()
+                    } else {
+                        b.next()
+                    },
+                (Some(_), None) => a.next(),
+                (None, _) => b.next(),
+            };
+            let Some(range) = next else { break };
+            Self::push(&mut v, range.clone());
+        }
+        RangeSet { v }
+    }
+
+    /// Returns the set of everything in both `self` and `other`.
+    #[must_use]
+    pub fn intersection(&self, other: &RangeSet<K>) -> RangeSet<K> {
+        let mut v = Vec::new();
+        let (mut a, mut b) = (self.v.iter().peekable(), other.v.iter().peekable());
+        while let (Some(ra), Some(rb)) = (a.peek(), b.peek()) {
+            let overlap = ra.start.max(rb.start)..ra.end.min(rb.end);
+            if overlap.start < overlap.end {
+                v.push(overlap);
+            }
+            // Move on from whichever range ends first; it cannot overlap anything else.
+            if ra.end <= rb.end {
+                a.next();
+            } else {
+                b.next();
+            }
+        }
+        RangeSet { v }
+    }
+
+    /// Returns the set of everything in `self` but not in `other`.
+    #[must_use]
+    pub fn difference(&self, other: &RangeSet<K>) -> RangeSet<K> {
+        let mut v = Vec::new();
+        let mut b = other.v.iter().peekable();
+        for ra in &self.v {
+            let mut start = ra.start;
+            // Skip the ranges of `other` that end before this range starts.
+            while b.next_if(|rb| rb.end <= start).is_some() {}
+            while let Some(rb) = b.peek() {
+                if rb.start >= ra.end {
+                    break;
+                }
+                if rb.start > start {
+                    v.push(start..rb.start);
+                }
+                start = start.max(rb.end);
+                if rb.end > ra.end {
+                    // This can still overlap the next range of `self`, so keep it around.
+                    break;
+                }
+                b.next();
+            }
+            if start < ra.end {
+                v.push(start..ra.end);
+            }
+        }
+        RangeSet { v }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ranges: &[(u64, u64)]) -> RangeSet {
+        let mut set = RangeSet::new();
+        for &(start, end) in ranges {
+            set.insert(start, end - start);
+        }
+        set
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut set = set(&[(2, 4), (8, 10), (4, 5), (12, 14)]);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![2..5, 8..10, 12..14]);
+        assert!(set.contains_range(2, 3));
+        assert!(!set.contains_range(4, 2));
+        assert!(set.overlaps(4, 2));
+        assert!(!set.overlaps(5, 3));
+        // Empty ranges contain nothing, even inside of the set.
+        assert!(set.contains_range(3, 0));
+        assert!(!set.overlaps(3, 0));
+
+        // Inserting a range spanning several others merges all of them.
+        set.insert(6, 6);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![2..5, 6..14]);
+
+        set.remove(4, 4);
+        set.remove(10, 1);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![2..4, 8..10, 11..14]);
+        set.remove(0, 20);
+        assert_eq!(set, RangeSet::new());
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = set(&[(0, 4), (6, 10), (12, 20)]);
+        let b = set(&[(2, 7), (10, 12), (14, 16), (18, 25)]);
+        assert_eq!(a.union(&b), set(&[(0, 25)]));
+        assert_eq!(a.intersection(&b), set(&[(2, 4), (6, 7), (14, 16), (18, 20)]));
+        assert_eq!(a.difference(&b), set(&[(0, 2), (7, 10), (12, 14), (16, 18)]));
+        assert_eq!(b.difference(&a), set(&[(4, 6), (10, 12), (20, 25)]));
+        assert_eq!(a.difference(&RangeSet::new()), a);
+    }
+}
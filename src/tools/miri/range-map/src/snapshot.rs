@@ -0,0 +1,191 @@
+//! A `RangeMap` that supports taking snapshots and rolling back to them.
+//! Rather than cloning the entire map on every snapshot, every mutation records the elements it
+//! is about to change in an undo log, which is replayed backwards on rollback.
+
+use alloc::vec::Vec;
+use core::ops;
+
+use super::{Elem, RangeKey, RangeMap, range_end, splice};
+
+/// An entry in the undo log: to undo the mutation, all elements covering `extent` are replaced
+/// by `old`. `old` covers exactly `extent`.
+#[derive(Clone, Debug)]
+struct UndoEntry<T, K> {
+    extent: ops::Range<K>,
+    old: Vec<Elem<T, K>>,
+}
+
+/// A snapshot of a `SnapshotRangeMap`, which can be rolled back to or committed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotId {
+    /// The length of the undo log when the snapshot was taken.
+    undo_len: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct SnapshotRangeMap<T, K = u64> {
+    map: RangeMap<T, K>,
+    undo_log: Vec<UndoEntry<T, K>>,
+    /// The number of snapshots that have been taken and not yet rolled back or committed.
+    /// Mutations are only recorded while this is non-zero.
+    open_snapshots: usize,
+}
+
+impl<T, K> ops::Deref for SnapshotRangeMap<T, K> {
+    type Target = RangeMap<T, K>;
+
+    fn deref(&self) -> &RangeMap<T, K> {
+        &self.map
+    }
+}
+
+impl<T, K: RangeKey> SnapshotRangeMap<T, K> {
+    /// Creates a new `SnapshotRangeMap` for the given size, and with the given initial value used
+    /// for the entire range.
+    pub fn new(size: K::Offset, init: T) -> SnapshotRangeMap<T, K> {
+        SnapshotRangeMap { map: RangeMap::new(size, init), undo_log: Vec::new(), open_snapshots: 0 }
+    }
+
+    /// Starts a new snapshot. Snapshots nest: they must be rolled back or committed in the
+    /// reverse order in which they were taken.
+    pub fn snapshot(&mut self) -> SnapshotId {
+        self.open_snapshots += 1;
+        SnapshotId { undo_len: self.undo_log.len() }
+    }
+
+    /// Undoes all mutations since the given snapshot was taken, and closes the snapshot.
+    pub fn rollback_to(&mut self, snapshot: SnapshotId) {
+        self.close_snapshot(snapshot);
+        while self.undo_log.len() > snapshot.undo_len {
+            let UndoEntry { extent, old } = self.undo_log.pop().unwrap();
+            let first_idx = self.map.find_offset(extent.start);
+            let last_idx = self.map.find_offset(extent.end - K::ONE);
+            splice(&mut self.map.v, first_idx..last_idx + 1, old);
+        }
+    }
+
+    /// Closes the given snapshot while keeping all mutations done since it was taken.
+    /// They can still be undone by rolling back to an outer snapshot.
+    pub fn commit(&mut self, snapshot: SnapshotId) {
+        self.close_snapshot(snapshot);
+        if self.open_snapshots == 0 {
+            // Nobody can roll back any more.
+            self.undo_log.clear();
+        }
+    }
+
+    fn close_snapshot(&mut self, snapshot: SnapshotId) {
+        assert!(self.open_snapshots > 0, "closing a snapshot that was never taken");
+        assert!(
+            self.undo_log.len() >= snapshot.undo_len,
+            "closing a snapshot that was already rolled back"
+        );
+        self.open_snapshots -= 1;
+    }
+
+    /// Records the elements covering the given range, if there is a snapshot to roll back to.
+    /// Must be called before mutating anything in that range, and the mutation must not affect
+    /// elements outside the range.
+    fn record(&mut self, offset: K::Offset, len: K::Offset)
+    where
+        T: Clone,
+    {
+        let offset = K::from_offset(offset);
+        let len = K::from_offset(len);
+        if self.open_snapshots == 0 || len == K::ZERO {
+            return;
+        }
+        let end = range_end(offset, len);
+        assert!(end <= self.map.size(), "mutating beyond the bounds of this RangeMap");
+        let first_idx = self.map.find_offset(offset);
+        let last_idx = self.map.find_offset(end - K::ONE);
+        let old = self.map.v[first_idx..=last_idx].to_vec();
+        let extent = old.first().unwrap().range.start..old.last().unwrap().range.end;
+        self.undo_log.push(UndoEntry { extent, old });
+    }
+
+    /// Provides mutable iteration over everything in the given range, see `RangeMap::iter_mut`.
+    /// Unlike `RangeMap::iter_mut`, this returns a plain iterator instead of a guard that merges
+    /// when dropped: equal neighbours within the range are merged right away, before the
+    /// mutation. The mutations made through the iterator are thus only merged by the next call
+    /// that touches the same elements.
+    pub fn iter_mut(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &mut T)>
+    where
+        T: Clone + PartialEq,
+    {
+        self.record(offset, len);
+        // `RangeMap::iter_mut` merges after the mutation, which might then merge elements outside
+        // of the range we recorded. Merging right away keeps all changes within it.
+        self.map.iter_mut_merging_by(offset, len, |a, b| a == b)
+    }
+
+    /// Overwrites everything in the given range with `data`, see `RangeMap::insert_range`.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T)
+    where
+        T: Clone,
+    {
+        self.record(offset, len);
+        self.map.insert_range(offset, len, data);
+    }
+
+    /// Copies data within the map, see `RangeMap::copy_within`.
+    pub fn copy_within(&mut self, src: K::Offset, dst: K::Offset, len: K::Offset)
+    where
+        T: Clone,
+    {
+        self.record(dst, len);
+        self.map.copy_within(src, dst, len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::read_range;
+    use super::*;
+
+    #[test]
+    fn rollback() {
+        let mut map = SnapshotRangeMap::<i32>::new(8, -1);
+        map.insert_range(2, 2, 42);
+        let before = read_range(0, 8, |i| *map.get(i));
+        // Nothing is recorded without a snapshot.
+        assert!(map.undo_log.is_empty());
+
+        let snapshot = map.snapshot();
+        map.insert_range(1, 2, 43);
+        for (_, x) in map.iter_mut(3, 3) {
+            *x += 1;
+        }
+        map.copy_within(0, 4, 4);
+        assert_ne!(read_range(0, 8, |i| *map.get(i)), before);
+        map.rollback_to(snapshot);
+        assert_eq!(read_range(0, 8, |i| *map.get(i)), before);
+        assert!(map.undo_log.is_empty());
+    }
+
+    #[test]
+    fn nested_snapshots() {
+        let mut map = SnapshotRangeMap::<i32>::new(8, -1);
+        let outer = map.snapshot();
+        map.insert_range(2, 2, 42);
+        let middle = read_range(0, 8, |i| *map.get(i));
+
+        let inner = map.snapshot();
+        map.insert_range(0, 8, 0);
+        map.rollback_to(inner);
+        assert_eq!(read_range(0, 8, |i| *map.get(i)), middle);
+
+        let inner = map.snapshot();
+        map.insert_range(3, 4, 43);
+        map.commit(inner);
+        assert_eq!(read_range(0, 8, |i| *map.get(i)), vec![-1, -1, 42, 43, 43, 43, 43, -1]);
+
+        // Rolling back the outer snapshot also undoes the committed inner one.
+        map.rollback_to(outer);
+        assert_eq!(read_range(0, 8, |i| *map.get(i)), vec![-1; 8]);
+    }
+}
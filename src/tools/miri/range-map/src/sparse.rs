@@ -0,0 +1,197 @@
+//! Implements a map from integer indices to data where not every index needs to be associated
+//! with data. Unlike `RangeMap`, ranges without data are not stored at all, which makes this
+//! better suited for large domains where most indices are never touched.
+
+use alloc::vec::Vec;
+use core::iter;
+use core::ops;
+
+use super::{Elem, RangeKey, range_end};
+
+#[derive(Clone, Debug)]
+pub struct SparseRangeMap<T, K = u64> {
+    /// The size of the domain of this map.
+    size: K,
+    /// The elements that are associated with data. They are sorted and do not overlap, but there
+    /// can be gaps between them.
+    v: Vec<Elem<T, K>>,
+}
+
+impl<T, K: RangeKey> SparseRangeMap<T, K> {
+    /// Creates a new `SparseRangeMap` for the given size, where no index is associated with data.
+    pub fn new(size: K::Offset) -> SparseRangeMap<T, K> {
+        SparseRangeMap { size: K::from_offset(size), v: Vec::new() }
+    }
+
+    /// Returns the index of the first element that ends after the given offset.
+    fn first_elem_after(&self, offset: K) -> usize {
+        self.v.partition_point(|elem| elem.range.end <= offset)
+    }
+
+    /// Returns the data associated with the given offset, or `None` if there is none.
+    pub fn get(&self, offset: K::Offset) -> Option<&T> {
+        let offset = K::from_offset(offset);
+        assert!(offset < self.size, "getting an offset beyond the bounds of this SparseRangeMap");
+        let elem = self.v.get(self.first_elem_after(offset))?;
+        (elem.range.start <= offset).then_some(&elem.data)
+    }
+
+    /// Provides read-only iteration over everything in the given range, including the gaps,
+    /// for which `None` is provided. The provided ranges are clipped to the given range.
+    pub fn iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, Option<&T>)> {
+        let mut pos = K::from_offset(offset);
+        let end = range_end(pos, K::from_offset(len));
+        assert!(end <= self.size, "iterating beyond the bounds of this SparseRangeMap");
+        let mut elems = self.v[self.first_elem_after(pos)..]
+            .iter()
+            .take_while(move |elem| elem.range.start < end)
+            .peekable();
+        iter::from_fn(move || {
+            if pos >= end {
+                return None;
+            }
+            let (range, data) = match elems.peek() {
+                Some(elem) if elem.range.start <= pos => {
+                    let elem = elems.next().unwrap();
+                    (pos..elem.range.end.min(end), Some(&elem.data))
+                }
+                next => {
+                    // We are in a gap that lasts until the next element.
+                    (pos..next.map_or(end, |elem| elem.range.start), None)
+                }
+            };
+            pos = range.end;
+            Some((range, data))
+        })
+    }
+
+    /// Splits the element containing the given offset (if any), such that the 2nd one starts at
+    /// that offset.
+    fn split_at(&mut self, offset: K)
+    where
+        T: Clone,
+    {
+        let idx = self.first_elem_after(offset);
+        if let Some(elem) = self.v.get_mut(idx) {
+            if elem.range.start < offset {
+                let second = Elem { range: offset..elem.range.end, data: elem.data.clone() };
+                elem.range.end = offset;
+                self.v.insert(idx + 1, second);
+            }
+        }
+    }
+
+    /// Splits the elements at the edges of the given range, and returns the indices of the
+    /// elements inside that range.
+    fn split_range(&mut self, offset: K::Offset, len: K::Offset) -> ops::Range<usize>
+    where
+        T: Clone,
+    {
+        let start = K::from_offset(offset);
+        let end = range_end(start, K::from_offset(len));
+        assert!(end <= self.size, "mutating beyond the bounds of this SparseRangeMap");
+        self.split_at(start);
+        self.split_at(end);
+        self.first_elem_after(start)..self.v.partition_point(|elem| elem.range.start < end)
+    }
+
+    /// Provides mutable iteration over the data in the given range. Gaps are skipped.
+    /// As a side-effect, this will split elements that are only partially hit by the given range.
+    /// Unlike `RangeMap::iter_mut`, this returns a plain iterator and never merges equal
+    /// neighbours, neither before nor after the mutation, so the map stays split afterwards.
+    pub fn iter_mut(
+        &mut self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &mut T)>
+    where
+        T: Clone,
+    {
+        let range = self.split_range(offset, len);
+        self.v[range].iter_mut().map(|elem| (elem.range.clone(), &mut elem.data))
+    }
+
+    /// Associates everything in the given range with `data`, filling any gaps.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T)
+    where
+        T: Clone,
+    {
+        let start = K::from_offset(offset);
+        let len = K::from_offset(len);
+        if len == K::ZERO {
+            // Nothing to insert.
+            return;
+        }
+        let range = self.split_range(start.to_offset(), len.to_offset());
+        self.v.splice(range, iter::once(Elem { range: start..start + len, data }));
+    }
+
+    /// Removes the data associated with the given range, leaving a gap.
+    pub fn remove_range(&mut self, offset: K::Offset, len: K::Offset)
+    where
+        T: Clone,
+    {
+        let range = self.split_range(offset, len);
+        self.v.drain(range);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::read_range;
+    use super::*;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut map = SparseRangeMap::<i32>::new(1u64 << 40);
+        assert!(map.v.is_empty());
+        map.insert_range(2, 4, 42);
+        map.insert_range(8, 2, 43);
+        assert_eq!(map.v.len(), 2);
+        assert_eq!(
+            read_range(0, 11, |i| map.get(i).copied().unwrap_or(-1)),
+            vec![-1, -1, 42, 42, 42, 42, -1, -1, 43, 43, -1]
+        );
+
+        // Filling a gap and overwriting parts of both neighbours.
+        map.insert_range(5, 4, 44);
+        assert_eq!(map.v.len(), 3);
+        assert_eq!(
+            read_range(4, 6, |i| map.get(i).copied().unwrap_or(-1)),
+            vec![42, 44, 44, 44, 44, 43]
+        );
+
+        // Punching a hole into the middle of an element.
+        map.remove_range(6, 2);
+        assert_eq!(map.v.len(), 4);
+        assert_eq!(
+            read_range(4, 6, |i| map.get(i).copied().unwrap_or(-1)),
+            vec![42, 44, -1, -1, 44, 43]
+        );
+    }
+
+    #[test]
+    fn iter() {
+        let mut map = SparseRangeMap::<i32>::new(20);
+        map.insert_range(2, 4, 42);
+        map.insert_range(8, 2, 43);
+        assert_eq!(
+            map.iter(3, 10).collect::<Vec<_>>(),
+            vec![(3..6, Some(&42)), (6..8, None), (8..10, Some(&43)), (10..13, None)]
+        );
+        assert_eq!(map.iter(0, 0).count(), 0);
+
+        // Mutation skips the gaps.
+        for (_, x) in map.iter_mut(4, 6) {
+            *x += 1;
+        }
+        assert_eq!(
+            read_range(2, 9, |i| map.get(i).copied().unwrap_or(-1)),
+            vec![42, 42, 43, 43, -1, -1, 44, 44, -1]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! A simple text format for `RangeMap`s, e.g. to describe the expected layout of a map in a test.
+//! Every line describes one range as `[start..end)=value`; empty lines are ignored. The values are
+//! formatted and parsed by user-provided functions.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::Write;
+use core::str::FromStr;
+
+use super::{InvalidRuns, RangeKey, RangeMap};
+
+/// The error returned by `RangeMap::parse_intervals`. Lines are counted from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseIntervalsError<K = u64> {
+    /// A line is not of the form `[start..end)=value`.
+    Syntax { line: usize },
+    /// The value parser rejected the value of a line.
+    Value { line: usize },
+    /// The ranges do not form a valid map.
+    Runs(InvalidRuns<K>),
+}
+
+impl<K: fmt::Debug> fmt::Display for ParseIntervalsError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseIntervalsError::Syntax { line } =>
+                write!(f, "line {line} is not of the form `[start..end)=value`"),
+            ParseIntervalsError::Value { line } => write!(f, "line {line} has an invalid value"),
+            ParseIntervalsError::Runs(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<T, K: RangeKey> RangeMap<T, K> {
+    /// Formats the maximal ranges with equal data in the interval format, one per line, using
+    /// `fmt_value` to format the data. The result does not depend on how the map is split.
+    pub fn to_interval_string_with(&self, mut fmt_value: impl FnMut(&T) -> String) -> String
+    where
+        T: PartialEq,
+        K: fmt::Display,
+    {
+        let mut s = String::new();
+        for (range, data) in self.iter_maximal() {
+            writeln!(s, "[{}..{})={}", range.start, range.end, fmt_value(data)).unwrap();
+        }
+        s
+    }
+
+    /// Like `to_interval_string_with`, but formats the data with its `Display` impl.
+    pub fn to_interval_string(&self) -> String
+    where
+        T: PartialEq + fmt::Display,
+        K: fmt::Display,
+    {
+        self.to_interval_string_with(|data| data.to_string())
+    }
+
+    /// Parses a map in the interval format, using `parse_value` to parse the data of every line.
+    /// The ranges must cover the map without gaps, starting at 0.
+    pub fn parse_intervals(
+        s: &str,
+        mut parse_value: impl FnMut(&str) -> Option<T>,
+    ) -> Result<Self, ParseIntervalsError<K>>
+    where
+        K: FromStr,
+    {
+        let mut runs = Vec::new();
+        for (idx, text) in s.lines().enumerate() {
+            let line = idx + 1;
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            let syntax = || ParseIntervalsError::Syntax { line };
+            let (range, value) = text.split_once('=').ok_or_else(syntax)?;
+            let range = range.trim().strip_prefix('[').and_then(|r| r.strip_suffix(')'));
+            let (start, end) = range.and_then(|r| r.split_once("..")).ok_or_else(syntax)?;
+            let start = start.trim().parse().map_err(|_| syntax())?;
+            let end = end.trim().parse().map_err(|_| syntax())?;
+            let data = parse_value(value.trim()).ok_or(ParseIntervalsError::Value { line })?;
+            runs.push((start..end, data));
+        }
+        RangeMap::try_from_runs(runs).map_err(ParseIntervalsError::Runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::sample_map;
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut map = sample_map();
+        map.insert_range(7, 5, 42);
+        let text = map.to_interval_string();
+        assert_eq!(text, "[0..5)=-1\n[5..12)=42\n[12..20)=-1\n");
+        let parsed = RangeMap::<i32>::parse_intervals(&text, |s| s.parse().ok()).unwrap();
+        assert!(parsed.semantic_eq(&map));
+        assert_eq!(
+            map.to_interval_string_with(|&x| format!("{x:x}")).lines().nth(1),
+            Some("[5..12)=2a")
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |s| RangeMap::<i32>::parse_intervals(s, |s| s.parse().ok());
+        assert_eq!(
+            parse("\n  [0..5) = 1\n\n[5..8)=2").unwrap().to_interval_string(),
+            "[0..5)=1\n[5..8)=2\n"
+        );
+        assert_eq!(parse("[0..5)=1\n0..8=2").unwrap_err(), ParseIntervalsError::Syntax { line: 2 });
+        assert_eq!(parse("[0..5)=x").unwrap_err(), ParseIntervalsError::Value { line: 1 });
+        assert_eq!(
+            parse("[0..5)=1\n[6..8)=2").unwrap_err(),
+            ParseIntervalsError::Runs(InvalidRuns::NotContiguous { start: 6, expected: 5 })
+        );
+    }
+}
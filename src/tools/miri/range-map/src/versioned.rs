@@ -0,0 +1,100 @@
+//! A mutable map that keeps all committed versions of itself around for read-only queries, e.g.
+//! to answer what was stored at some offset a few steps ago. Built on `PersistentRangeMap`, so
+//! versions share all chunks that were not mutated between them.
+
+use alloc::vec::Vec;
+use core::ops;
+
+use super::{PersistentRangeMap, RangeKey};
+
+/// A version of a `VersionedRangeMap`, returned by `commit`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    /// The index of this version in `VersionedRangeMap::versions`.
+    idx: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct VersionedRangeMap<T, K = u64> {
+    current: PersistentRangeMap<T, K>,
+    /// All committed versions, oldest first.
+    versions: Vec<PersistentRangeMap<T, K>>,
+}
+
+impl<T, K: RangeKey> VersionedRangeMap<T, K> {
+    /// Creates a new `VersionedRangeMap` for the given size, and with the given initial value
+    /// used for the entire range. No version is committed yet.
+    pub fn new(size: K::Offset, init: T) -> VersionedRangeMap<T, K> {
+        VersionedRangeMap { current: PersistentRangeMap::new(size, init), versions: Vec::new() }
+    }
+
+    /// Commits the current state of the map, which can then be queried with `get_at` and
+    /// `iter_at` no matter how the map is mutated afterwards.
+    pub fn commit(&mut self) -> Version {
+        self.versions.push(self.current.clone());
+        Version { idx: self.versions.len() - 1 }
+    }
+
+    /// Returns the number of committed versions.
+    pub fn num_versions(&self) -> usize {
+        self.versions.len()
+    }
+
+    /// Returns the data currently associated with the given offset.
+    pub fn get(&self, offset: K::Offset) -> &T {
+        self.current.get(offset)
+    }
+
+    /// Provides read-only iteration over everything in the given range of the current state.
+    pub fn iter(
+        &self,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        self.current.iter(offset, len)
+    }
+
+    /// Returns the data associated with the given offset in the given version.
+    pub fn get_at(&self, version: Version, offset: K::Offset) -> &T {
+        self.versions[version.idx].get(offset)
+    }
+
+    /// Provides read-only iteration over everything in the given range of the given version.
+    pub fn iter_at(
+        &self,
+        version: Version,
+        offset: K::Offset,
+        len: K::Offset,
+    ) -> impl Iterator<Item = (ops::Range<K>, &T)> {
+        self.versions[version.idx].iter(offset, len)
+    }
+
+    /// Overwrites everything in the given range with `data`. Committed versions are not affected.
+    pub fn insert_range(&mut self, offset: K::Offset, len: K::Offset, data: T)
+    where
+        T: Clone + PartialEq,
+    {
+        self.current = self.current.insert_range(offset, len, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_travel() {
+        let mut map = VersionedRangeMap::<i32>::new(20, -1);
+        let v0 = map.commit();
+        map.insert_range(5, 5, 42);
+        let v1 = map.commit();
+        map.insert_range(8, 4, 43);
+        assert_eq!(map.num_versions(), 2);
+        assert!(v0 < v1);
+        assert_eq!(*map.get_at(v0, 8), -1);
+        assert_eq!(*map.get_at(v1, 8), 42);
+        assert_eq!(*map.get(8), 43);
+        assert_eq!(map.iter_at(v1, 6, 6).collect::<Vec<_>>(), vec![(5..10, &42), (10..20, &-1)]);
+        assert_eq!(map.iter(6, 6).collect::<Vec<_>>(), vec![(5..8, &42), (8..12, &43)]);
+    }
+}
@@ -492,7 +492,7 @@ fn new(
         let stack = Stack::new(item);
 
         Stacks {
-            stacks: RangeMap::new(size, stack),
+            stacks: RangeMap::new(size.bytes(), stack),
             history: AllocHistory::new(id, item, machine),
             exposed_tags: FxHashSet::default(),
             modified_since_last_gc: false,
@@ -511,7 +511,9 @@ fn for_each(
         ) -> InterpResult<'tcx>,
     ) -> InterpResult<'tcx> {
         self.modified_since_last_gc = true;
-        for (stack_range, stack) in self.stacks.iter_mut(range.start, range.size) {
+        for (stack_range, stack) in
+            &mut self.stacks.iter_mut(range.start.bytes(), range.size.bytes())
+        {
             let mut dcx = dcx_builder.build(&mut self.history, Size::from_bytes(stack_range.start));
             f(stack, &mut dcx, &mut self.exposed_tags)?;
             dcx_builder = dcx.unbuild();
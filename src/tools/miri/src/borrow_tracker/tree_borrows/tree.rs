@@ -298,7 +298,7 @@ pub fn new(root_tag: BorTag, size: Size, span: Span) -> Self {
         let rperms = {
             let mut perms = UniValMap::default();
             perms.insert(root_idx, LocationState::new(root_perm).with_access());
-            RangeMap::new(size, perms)
+            RangeMap::new(size.bytes(), perms)
         };
         Self { root: root_idx, nodes, rperms, tag_mapping }
     }
@@ -332,7 +332,8 @@ pub fn new_child(
         self.nodes.get_mut(parent_idx).unwrap().children.push(idx);
         // Initialize perms
         let perm = LocationState::new(default_initial_perm).with_access();
-        for (_perms_range, perms) in self.rperms.iter_mut(reborrow_range.start, reborrow_range.size)
+        for (_perms_range, perms) in
+            &mut self.rperms.iter_mut(reborrow_range.start.bytes(), reborrow_range.size.bytes())
         {
             perms.insert(idx, perm);
         }
@@ -357,7 +358,9 @@ pub fn dealloc(
             span,
             diagnostics::AccessCause::Dealloc,
         )?;
-        for (perms_range, perms) in self.rperms.iter_mut(access_range.start, access_range.size) {
+        for (perms_range, perms) in
+            &mut self.rperms.iter_mut(access_range.start.bytes(), access_range.size.bytes())
+        {
             TreeVisitor { nodes: &mut self.nodes, tag_mapping: &self.tag_mapping, perms }
                 .traverse_parents_this_children_others(
                     tag,
@@ -401,7 +404,9 @@ pub fn perform_access(
         span: Span,                             // diagnostics
         access_cause: diagnostics::AccessCause, // diagnostics
     ) -> InterpResult<'tcx> {
-        for (perms_range, perms) in self.rperms.iter_mut(access_range.start, access_range.size) {
+        for (perms_range, perms) in
+            &mut self.rperms.iter_mut(access_range.start.bytes(), access_range.size.bytes())
+        {
             TreeVisitor { nodes: &mut self.nodes, tag_mapping: &self.tag_mapping, perms }
                 .traverse_parents_this_children_others(
                     tag,
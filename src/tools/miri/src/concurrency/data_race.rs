@@ -735,7 +735,7 @@ pub fn new_allocation(
         };
         VClockAlloc {
             alloc_ranges: RefCell::new(RangeMap::new(
-                len,
+                len.bytes(),
                 MemoryCellClocks::new(alloc_timestamp, alloc_index),
             )),
         }
@@ -852,7 +852,7 @@ pub(super) fn race_free_with_atomic(
         if global.race_detecting() {
             let (_, thread_clocks) = global.current_thread_state(thread_mgr);
             let alloc_ranges = self.alloc_ranges.borrow();
-            for (_, mem_clocks) in alloc_ranges.iter(range.start, range.size) {
+            for (_, mem_clocks) in alloc_ranges.iter(range.start.bytes(), range.size.bytes()) {
                 if !mem_clocks.race_free_with_atomic(&thread_clocks) {
                     return false;
                 }
@@ -878,7 +878,7 @@ pub fn read<'tcx>(
             let (index, mut thread_clocks) = global.current_thread_state_mut(&machine.threads);
             let mut alloc_ranges = self.alloc_ranges.borrow_mut();
             for (mem_clocks_range, mem_clocks) in
-                alloc_ranges.iter_mut(access_range.start, access_range.size)
+                &mut alloc_ranges.iter_mut(access_range.start.bytes(), access_range.size.bytes())
             {
                 if let Err(DataRace) =
                     mem_clocks.read_race_detect(&mut thread_clocks, index, current_span)
@@ -913,8 +913,10 @@ fn unique_access<'tcx>(
         let global = machine.data_race.as_mut().unwrap();
         if global.race_detecting() {
             let (index, mut thread_clocks) = global.current_thread_state_mut(&machine.threads);
-            for (mem_clocks_range, mem_clocks) in
-                self.alloc_ranges.get_mut().iter_mut(access_range.start, access_range.size)
+            for (mem_clocks_range, mem_clocks) in &mut self
+                .alloc_ranges
+                .get_mut()
+                .iter_mut(access_range.start.bytes(), access_range.size.bytes())
             {
                 if let Err(DataRace) = mem_clocks.write_race_detect(
                     &mut thread_clocks,
@@ -1153,8 +1155,10 @@ fn validate_atomic_op<A: Debug + Copy>(
                     &this.machine.threads,
                     current_span,
                     |index, mut thread_clocks| {
-                        for (mem_clocks_range, mem_clocks) in
-                            alloc_meta.alloc_ranges.borrow_mut().iter_mut(base_offset, size)
+                        for (mem_clocks_range, mem_clocks) in &mut alloc_meta
+                            .alloc_ranges
+                            .borrow_mut()
+                            .iter_mut(base_offset.bytes(), size.bytes())
                         {
                             if let Err(DataRace) = op(mem_clocks, &mut thread_clocks, index, atomic)
                             {
@@ -1182,7 +1186,7 @@ fn validate_atomic_op<A: Debug + Copy>(
                 // Log changes to atomic memory.
                 if log::log_enabled!(log::Level::Trace) {
                     for (_offset, mem_clocks) in
-                        alloc_meta.alloc_ranges.borrow().iter(base_offset, size)
+                        alloc_meta.alloc_ranges.borrow().iter(base_offset.bytes(), size.bytes())
                     {
                         log::trace!(
                             "Updated atomic memory({:?}, size={}) to {:#?}",
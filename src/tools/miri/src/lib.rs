@@ -70,7 +70,6 @@
 mod machine;
 mod mono_hash_map;
 mod operator;
-mod range_map;
 mod shims;
 mod tag_gc;
 
@@ -81,6 +80,8 @@
 // Resolve ambiguity.
 pub use rustc_const_eval::interpret::{self, AllocMap, PlaceTy, Provenance as _};
 
+pub use range_map::RangeMap;
+
 pub use crate::shims::dlsym::{Dlsym, EvalContextExt as _};
 pub use crate::shims::env::{EnvVars, EvalContextExt as _};
 pub use crate::shims::foreign_items::EvalContextExt as _;
@@ -119,7 +120,6 @@
 };
 pub use crate::mono_hash_map::MonoHashMap;
 pub use crate::operator::EvalContextExt as _;
-pub use crate::range_map::RangeMap;
 pub use crate::tag_gc::{EvalContextExt as _, VisitTags};
 
 /// Insert rustc arguments at the beginning of the argument list that Miri wants to be